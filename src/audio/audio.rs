@@ -13,11 +13,12 @@ use super::buffer_player::SamplesBuffer;
 use super::buffer_player::AudioController;
 
 use crate::chart::Chart;
+use crate::chart::HitSoundKind;
 
 pub fn update_audio(progress: &mut AudioProgressBar, music: &mut AudioManager, chart: &mut Chart) {
     if is_key_pressed(KeyCode::Space) {
         music.toggle();
-        chart.reset_hitsound(music.get_time() * 1000.0);
+        chart.reset_hitsound(music.get_time() as f64 * 1000.0);
     }
     let (delta_x, delta_y) = mouse_wheel();
     if delta_y != 0.0 && !music.is_playing() {
@@ -41,7 +42,7 @@ pub fn update_audio(progress: &mut AudioProgressBar, music: &mut AudioManager, c
     let update: bool = progress.update(music.is_playing());
     if update {
         music.set_time(music.get_duration() * progress.get_progress());
-        chart.reset_hitsound(music.get_time() * 1000.0);
+        chart.reset_hitsound(music.get_time() as f64 * 1000.0);
     }
     else {
         progress.set_progress(music.get_time() / music.get_duration());
@@ -49,6 +50,16 @@ pub fn update_audio(progress: &mut AudioProgressBar, music: &mut AudioManager, c
     progress.draw();
 }
 
+// 播放时把到时间的打击音放出来，每帧在update_audio之后调用
+pub fn update_hitsound(hitsound: &HitSoundManager, music: &AudioManager, chart: &mut Chart) {
+    if !music.is_playing() {
+        return;
+    }
+    for kind in chart.take_due_hitsounds(music.get_time() as f64 * 1000.0) {
+        hitsound.play_kind(kind);
+    }
+}
+
 pub struct HitSoundPlayer {
     pub audio_data: Arc<Vec<u8>>,
     pub stream_handle: OutputStreamHandle,
@@ -181,6 +192,18 @@ impl HitSoundManager {
         active_sinks.retain(|s| !s.empty());
     }
 
+    // 按打击音种类播放，加新的种类时在这里加上对应的音效
+    pub fn play_kind(&self, kind: HitSoundKind) {
+        match kind {
+            HitSoundKind::Tap => self.play_tap(),
+            HitSoundKind::Flick => self.play_flick(),
+            HitSoundKind::Slide => self.play_slide(),
+            HitSoundKind::RotateLeft => self.play_rotate_L(),
+            HitSoundKind::RotateRight => self.play_rotate_R(),
+            HitSoundKind::Catch => self.play_catch(),
+        }
+    }
+
     pub fn play_tap(&self) {
         self.play(&self.tap_hitsound);
     }
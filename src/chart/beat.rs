@@ -0,0 +1,153 @@
+// beat.rs
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+// 能接受的吸附误差（拍），1ms在300bpm下大约是0.005拍
+pub const BEAT_SNAP_TOLERANCE: f64 = 0.005;
+
+// 用拍数表示的位置：beat + numerator / denominator
+// 例如第3拍后面的3/4处就是 beat: 3, numerator: 3, denominator: 4
+//
+// note上存的是从第0拍开始数的拍数，不是小节数，这样改拍号时note不会移动，改bpm后重新算time也只需要拍数
+// 需要按"小节 + 小节内位置"显示或输入时，用Chart::beat_position_to_measure_position和
+// Chart::measure_position_to_beat_position按当前拍号换算成MeasurePosition
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+pub struct BeatPosition {
+    pub beat: i64,
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl BeatPosition {
+    pub fn new(beat: i64, numerator: i32, denominator: i32) -> Self {
+        let mut position = Self { beat, numerator, denominator: denominator.max(1) };
+        position.normalize();
+        position
+    }
+
+    // 把小数拍数吸附到分母不超过max_denominator的分数上
+    // 优先取误差在BEAT_SNAP_TOLERANCE以内的最小分母，都不行就取误差最小的
+    pub fn from_beat(beat: f64, max_denominator: i32) -> Self {
+        let max_denominator: i32 = max_denominator.max(1);
+        let whole: f64 = beat.floor();
+        let fract: f64 = beat - whole;
+
+        let mut best: (i32, i32) = (0, 1);
+        let mut best_error: f64 = f64::MAX;
+        for denominator in 1..=max_denominator {
+            let numerator: f64 = (fract * denominator as f64).round();
+            let error: f64 = (fract - numerator / denominator as f64).abs();
+            if error <= BEAT_SNAP_TOLERANCE {
+                best = (numerator as i32, denominator);
+                break;
+            }
+            if error < best_error {
+                best_error = error;
+                best = (numerator as i32, denominator);
+            }
+        }
+        Self::new(whole as i64, best.0, best.1)
+    }
+
+    pub fn to_beat(&self) -> f64 {
+        self.beat as f64 + self.numerator as f64 / self.denominator as f64
+    }
+
+    // 约分，并保证0 <= numerator < denominator
+    fn normalize(&mut self) {
+        if self.denominator < 0 {
+            self.denominator = -self.denominator;
+            self.numerator = -self.numerator;
+        }
+        self.beat += self.numerator.div_euclid(self.denominator) as i64;
+        self.numerator = self.numerator.rem_euclid(self.denominator);
+
+        let mut a: i32 = self.numerator;
+        let mut b: i32 = self.denominator;
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        if a > 1 {
+            self.numerator /= a;
+            self.denominator /= a;
+        }
+    }
+}
+
+// 用小节表示的位置：measure + numerator / denominator，分数是小节内的位置
+// 例如3/4拍下第2小节的第2拍就是 measure: 2, numerator: 1, denominator: 3
+// 小节数的规则见timing.rs，拍号改动后同一个拍数位置对应的小节位置会变，所以只用于显示和输入
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeasurePosition {
+    pub measure: i64,
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl MeasurePosition {
+    pub fn new(measure: i64, numerator: i32, denominator: i32) -> Self {
+        // 约分的规则和BeatPosition一样
+        let position: BeatPosition = BeatPosition::new(measure, numerator, denominator);
+        Self { measure: position.beat, numerator: position.numerator, denominator: position.denominator }
+    }
+
+    // 把小数小节数吸附到分母不超过max_denominator的分数上，规则同BeatPosition::from_beat
+    pub fn from_measure(measure: f64, max_denominator: i32) -> Self {
+        let position: BeatPosition = BeatPosition::from_beat(measure, max_denominator);
+        Self { measure: position.beat, numerator: position.numerator, denominator: position.denominator }
+    }
+
+    pub fn to_measure(&self) -> f64 {
+        self.measure as f64 + self.numerator as f64 / self.denominator as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::bpm::BPM;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::timesignature::TimeSignature;
+
+    // 120bpm一拍500ms，前两小节3/4拍，第6拍（3000ms）起4/4拍
+    fn chart() -> Chart {
+        let mut data: ChartData = ChartData::new();
+        data.bpm = vec![BPM::new(0.0, 120.0)];
+        data.time_signature = vec![TimeSignature::new(0.0, 3, 4), TimeSignature::new(3000.0, 4, 4)];
+        Chart::new(data)
+    }
+
+    #[test]
+    fn positions_are_normalized() {
+        assert_eq!(BeatPosition::new(1, 5, 4), BeatPosition::new(2, 1, 4));
+        assert_eq!(BeatPosition::new(1, -1, 2), BeatPosition::new(0, 1, 2));
+        assert_eq!(BeatPosition::new(0, 2, 4), BeatPosition::new(0, 1, 2));
+        assert_eq!(BeatPosition::from_beat(2.3334, 16), BeatPosition::new(2, 1, 3));
+        assert_eq!(MeasurePosition::new(3, 6, 8), MeasurePosition::new(3, 3, 4));
+    }
+
+    #[test]
+    fn measure_positions_follow_time_signature() {
+        let chart: Chart = chart();
+        let cases: [(BeatPosition, MeasurePosition); 4] = [
+            (BeatPosition::new(0, 0, 1), MeasurePosition::new(0, 0, 1)),
+            (BeatPosition::new(4, 0, 1), MeasurePosition::new(1, 1, 3)),
+            (BeatPosition::new(6, 0, 1), MeasurePosition::new(2, 0, 1)),
+            (BeatPosition::new(7, 1, 2), MeasurePosition::new(2, 3, 8)),
+        ];
+        for (beat, measure) in cases {
+            assert_eq!(chart.beat_position_to_measure_position(&beat, 16), measure);
+            assert_eq!(chart.measure_position_to_beat_position(&measure, 16), beat);
+        }
+    }
+
+    #[test]
+    fn changing_time_signature_keeps_beats() {
+        let mut chart: Chart = chart();
+        let beat: BeatPosition = BeatPosition::new(7, 1, 2);
+        chart.edit(|data| data.time_signature = vec![TimeSignature::new(0.0, 4, 4)]);
+        assert_eq!(chart.beat_position_to_chart_time(&beat), 3750.0);
+        assert_eq!(chart.beat_position_to_measure_position(&beat, 16), MeasurePosition::new(1, 7, 8));
+    }
+}
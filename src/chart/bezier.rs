@@ -0,0 +1,181 @@
+// bezier.rs
+// trail和slide用的三次贝塞尔曲线：x方向的控制点是control_a和1 - control_b，y方向固定是smoothstep（-2t^3 + 3t^2）
+// 已知x求y要先解出曲线参数t：
+//     先用牛顿法，不收敛、斜率太小或者t跑出[0, 1]时退回二分（和以前的get_y_from_x一样的20次二分）
+//     同一条曲线要算很多次时可以先建查找表，用表里插值出来的t当牛顿法的初值，一般一两步就收敛
+// x不在[0, 1]里时牛顿法一定会跑出去，结果和二分一样贴在0或1上
+// 控制点不在[0, 1]里时（曲率不在0到100之间）x对t不单调，同一个x可能有几个t，牛顿法找到的不一定是二分找到的那个
+// 这时候直接用二分，结果和以前一样
+
+pub const TABLE_SIZE: usize = 33;
+
+#[derive(Clone, Copy)]
+pub struct BezierCurve {
+    control_a: f32,
+    control_b: f32,
+    table: Option<[f32; TABLE_SIZE]>, // x为i / (TABLE_SIZE - 1)时的t
+}
+
+impl BezierCurve {
+    pub fn new(control_a: f32, control_b: f32) -> Self {
+        Self { control_a, control_b, table: None }
+    }
+
+    // 两个控制点都在[0, 1]里时x对t单调
+    pub fn is_monotonic(&self) -> bool {
+        (0.0..=1.0).contains(&self.control_a) && (0.0..=1.0).contains(&self.control_b)
+    }
+
+    // 建好查找表的同一条曲线，建表要二分TABLE_SIZE次，同一条曲线要用很多次时才划算
+    // 不单调的曲线只用二分，不建表
+    pub fn with_table(mut self) -> Self {
+        if !self.is_monotonic() {
+            return self;
+        }
+        self.table = Some(std::array::from_fn(|i| self.solve_by_bisection(i as f32 / (TABLE_SIZE - 1) as f32)));
+        self
+    }
+
+    pub fn has_table(&self) -> bool {
+        self.table.is_some()
+    }
+
+    pub fn x_at(&self, t: f32) -> f32 {
+        let (a, b): (f32, f32) = (self.control_a, 1.0 - self.control_b);
+        t * (3.0 * a - 6.0 * a * t + 3.0 * b * t + t * t + 3.0 * a * t * t - 3.0 * b * t * t)
+    }
+
+    // x对t的导数
+    fn slope_at(&self, t: f32) -> f32 {
+        let (a, b): (f32, f32) = (self.control_a, 1.0 - self.control_b);
+        3.0 * a - 12.0 * a * t + 6.0 * b * t + 3.0 * t * t + 9.0 * a * t * t - 9.0 * b * t * t
+    }
+
+    pub fn y_at_t(t: f32) -> f32 {
+        -2.0 * t * t * t + 3.0 * t * t
+    }
+
+    // 已知x求y，y在0到1之间
+    pub fn y_at(&self, x: f32) -> f32 {
+        Self::y_at_t(self.solve_t(x))
+    }
+
+    // 已知x求t
+    pub fn solve_t(&self, x: f32) -> f32 {
+        if !self.is_monotonic() {
+            return self.solve_by_bisection(x);
+        }
+        let guess: f32 = match &self.table {
+            Some(table) if (0.0..=1.0).contains(&x) => {
+                let position: f32 = x * (TABLE_SIZE - 1) as f32;
+                let index: usize = (position as usize).min(TABLE_SIZE - 2);
+                let fract: f32 = position - index as f32;
+                table[index] + (table[index + 1] - table[index]) * fract
+            },
+            _ => x.clamp(0.0, 1.0),
+        };
+        self.solve_by_newton(x, guess).unwrap_or_else(|| self.solve_by_bisection(x))
+    }
+
+    fn solve_by_newton(&self, x: f32, mut t: f32) -> Option<f32> {
+        // 按t的步长判断收敛，曲线在拐点附近很平时x的误差很小但t还差得远
+        for _ in 0..8 {
+            let slope: f32 = self.slope_at(t);
+            if slope.abs() < 1e-6 {
+                return None;
+            }
+            let step: f32 = (self.x_at(t) - x) / slope;
+            t -= step;
+            if !(0.0..=1.0).contains(&t) {
+                return None;
+            }
+            if step.abs() < 1e-6 {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn solve_by_bisection(&self, x: f32) -> f32 {
+        // Approximated t value based on x
+        // binary search
+        let mut upper_time: f32 = 1.0; // tail
+        let mut lower_time: f32 = 0.0; // head
+        let mut time: f32 = 0.0; // compare time
+
+        // Do a binary search 20 time to approximate the target time
+        // For each loop, the compare time = (head + tail) / 2
+        // And then compare the X at compare time
+        for _ in 0..20 {
+            time = (upper_time + lower_time) / 2.0;
+            let search_x: f32 = self.x_at(time);
+            if search_x < x {
+                lower_time = time;
+            } else {
+                upper_time = time;
+            }
+        }
+        time
+    }
+}
+
+pub fn get_y_from_x(start: f32, end: f32, control_a: f32, control_b: f32, x: f32) -> f32 {
+    BezierCurve::new(control_a, control_b).y_at(x) * (end - start) + start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 曲率0到100每隔5取一个，加上超出范围的几个
+    fn controls() -> Vec<f32> {
+        (0..=20).map(|i| i as f32 * 0.05).chain([-0.5, -0.01, 1.01, 1.5, 3.0]).collect()
+    }
+
+    fn xs() -> impl Iterator<Item = f32> {
+        (0..=400).map(|i| i as f32 / 400.0)
+    }
+
+    #[test]
+    fn newton_agrees_with_bisection() {
+        for &a in &controls() {
+            for &b in &controls() {
+                let curve: BezierCurve = BezierCurve::new(a, b);
+                let table: BezierCurve = curve.with_table();
+                assert_eq!(table.has_table(), curve.is_monotonic());
+                for x in xs() {
+                    let expected: f32 = BezierCurve::y_at_t(curve.solve_by_bisection(x));
+                    for solved in [curve.y_at(x), table.y_at(x)] {
+                        if curve.is_monotonic() {
+                            assert!((solved - expected).abs() < 1e-4, "a {} b {} x {}: {} != {}", a, b, x, solved, expected);
+                        } else {
+                            assert_eq!(solved, expected, "a {} b {} x {}", a, b, x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn newton_solves_x() {
+        for &a in controls().iter().filter(|a| (0.0..=1.0).contains(*a)) {
+            for &b in controls().iter().filter(|b| (0.0..=1.0).contains(*b)) {
+                let curve: BezierCurve = BezierCurve::new(a, b).with_table();
+                for x in xs() {
+                    let t: f32 = curve.solve_t(x);
+                    assert!((0.0..=1.0).contains(&t));
+                    assert!((curve.x_at(t) - x).abs() < 1e-4, "a {} b {} x {}", a, b, x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn outside_range_sticks_to_ends() {
+        let curve: BezierCurve = BezierCurve::new(0.3, 0.7).with_table();
+        assert!(curve.y_at(-0.5) < 1e-4);
+        assert!(curve.y_at(1.5) > 1.0 - 1e-4);
+        assert_eq!(get_y_from_x(10.0, 30.0, 0.3, 0.7, 0.0), 10.0);
+    }
+}
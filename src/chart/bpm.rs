@@ -1,18 +1,19 @@
 // bpm.rs
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct BPM {
-    pub time: f32,
-    pub bpm: f32,
+    pub time: f64,
+    pub bpm: f64,
 }
 
 impl BPM {
-    pub fn new(time: f32, bpm: f32) -> Self {
+    pub fn new(time: f64, bpm: f64) -> Self {
         Self { time, bpm }
     }
 
-    pub fn real_time(&self) -> f32 {
+    pub fn real_time(&self) -> f64 {
         self.time / (1000.0)
     }
 }
\ No newline at end of file
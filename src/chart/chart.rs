@@ -1,9 +1,15 @@
 // chart/chart.rs
 
+// Header:
+// 		# Version version
+// 		# Offset offset
+// 		其他以#开头的行原样保存到metadata
 // BPM:
 // 		time/BPM
+// 	TimeSignature（官谱没有，本工具的扩展，没有拍号时不输出）:
+// 		time/numerator/denominator
 // 	Speed:
-// 		time/speed/smooth
+// 		time/speed/smooth（smooth为11时后面还有x1/y1/x2/y2，见easing.rs）
 // 	Note:
 // 		0(tap)/time/degree
 // 		1(flick)/time/degree
@@ -13,49 +19,106 @@
 //      6(bomb)/time/degree
 // 		11(trail)/time/degree/delta/prev_curv/next_curv
 
-use core::time;
-use std::char;
 use std::fs::File;
-use std::io::{self, BufRead, Error};
+use std::io::{self, BufRead};
 use std::io::{Write, Read};
 use std::path::Path;
 use std::io::Result as IoResult;
-use std::rc::Rc;
+use std::fmt;
+use std::str::FromStr;
 
 
-use serde::{de, Deserialize, Serialize};
-use serde_json::{Result, Value, json};
+use serde::Serialize;
+use serde_json::Value;
 use serde_json::ser::{Serializer, PrettyFormatter};
 
-use log::{info, warn, error};
-
+use super::error::ChartError;
+use super::chartdata::ChartData;
+use super::search::{SearchMode, find_pos_by_time};
+use super::timeline::ChartTimeline;
+use super::schema::{self, ChartJson};
+use super::format::{WriteOptions, SourceFormat, RawLines, BOM, format_number, bpm_line, time_signature_line, speed_line, note_line};
+use super::parse::{ParseOptions, ParseReport, Diagnostic, LineFields, json_error, from_json_value};
+use super::lint;
 use super::bpm::BPM;
+use super::timesignature::TimeSignature;
 use super::speed::Speed;
+use super::easing::{Easing, BEZIER_CODE, EXTENSION_HEADER};
 use super::note::Note;
+use super::beat::{BeatPosition, MeasurePosition};
 
 use super::note::Tap;
 use super::note::Flick;
 use super::note::Slide;
+use super::note::SlideType;
 use super::note::Rotate;
 use super::note::Catch;
 use super::note::Trail;
 use super::note::Bomb;
 
-use super::speeddistance::{self, SpeedDistance};
-use super::traildistance::{self, TrailDistance};
+use super::traildistance::TrailDistance;
+
+// 打击音的种类，和谱面里的note类型编号不一样，code是打击音自己的编号
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HitSoundKind {
+    Tap,
+    Flick,
+    Slide, // slide开头（tap头和flick头除外）和中间的tick
+    RotateLeft,
+    RotateRight,
+    Catch,
+}
+
+impl HitSoundKind {
+    pub fn code(&self) -> i32 {
+        match self {
+            HitSoundKind::Tap => 0,
+            HitSoundKind::Flick => 1,
+            HitSoundKind::Slide => 2,
+            HitSoundKind::RotateLeft => 3,
+            HitSoundKind::RotateRight => 4,
+            HitSoundKind::Catch => 5,
+        }
+    }
+
+    // slide开头的打击音，tap头和flick头跟tap、flick一样
+    pub fn from_slide_head(slidetype: SlideType) -> Self {
+        match slidetype {
+            SlideType::TapHead => HitSoundKind::Tap,
+            SlideType::FlickHead => HitSoundKind::Flick,
+            SlideType::Small | SlideType::Big => HitSoundKind::Slide,
+        }
+    }
+}
+
+impl TryFrom<i32> for HitSoundKind {
+    type Error = String;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(HitSoundKind::Tap),
+            1 => Ok(HitSoundKind::Flick),
+            2 => Ok(HitSoundKind::Slide),
+            3 => Ok(HitSoundKind::RotateLeft),
+            4 => Ok(HitSoundKind::RotateRight),
+            5 => Ok(HitSoundKind::Catch),
+            _ => Err(format!("unknown hitsound kind `{}`", code)),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct HitSound {
-    pub time: f32,
-    pub note_type: i32,
+    pub time: f64,
+    pub kind: HitSoundKind,
     pub played: bool,
-} // 这里要注意，note_type跟谱面里不太一样，0是tap，1是flick，2是slide(谱面里2和3是slise)，3和4是rotateL和rotateR，5是catch
+}
 
 impl HitSound {
-    pub fn new(time: f32, note_type: i32) -> Self {
+    pub fn new(time: f64, kind: HitSoundKind) -> Self {
         Self {
             time,
-            note_type,
+            kind,
             played: false,
         }
     }
@@ -65,32 +128,29 @@ impl HitSound {
     }
 }
 
+// 谱面数据和由它生成的timeline放在一起，谱面只能通过edit等函数修改，改完会重新生成timeline
 #[derive(Clone)]
 pub struct Chart {
-    pub version: i32,
-    pub offset: f32,
-    pub bpm: Vec<BPM>,
-    pub speed: Vec<Speed>,
-    pub note: Vec<Note>,
-    pub speed_distance: Vec<SpeedDistance>,
-    pub speed_distance_plain: Vec<SpeedDistance>,
-    pub trail_distance: Vec<TrailDistance>,
-    pub trail_distance_plain: Vec<TrailDistance>,
-
-    pub hitsound_list: Vec<HitSound>,
-
-    pub phone_trail_distance: Vec<TrailDistance>,
-    // pub single_trail_distance: Vec<TrailDistance>,
+    data: ChartData,
+    timeline: ChartTimeline,
+
+    pub hitsound_list: Vec<HitSound>, // 由谱面生成，played是播放时的状态
 }
 
 pub struct ChartProperties {
-    pub start_chart_time: f32,
-    pub end_chart_time: f32,
-    pub start_distance: f32,
-    pub end_distance: f32,
-    pub show_distance: f32,
+    pub start_chart_time: f64,
+    pub end_chart_time: f64,
+    pub start_distance: f64,
+    pub end_distance: f64,
+    pub show_distance: f64,
     pub cur_degree: f32,
 }
+impl Default for ChartProperties {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ChartProperties {
     pub fn new() -> Self {
         Self {
@@ -103,7 +163,7 @@ impl ChartProperties {
         }
     }
     
-    pub fn init(start_chart_time: f32, end_chart_time: f32, start_distance: f32, end_distance: f32, show_distance: f32, cur_degree: f32) -> Self {
+    pub fn init(start_chart_time: f64, end_chart_time: f64, start_distance: f64, end_distance: f64, show_distance: f64, cur_degree: f32) -> Self {
         Self {
             start_chart_time,
             end_chart_time,
@@ -116,1174 +176,809 @@ impl ChartProperties {
 }
 
 
+// official_records的结果：(表头, BPM, TimeSignature, Speed, Note)每一行的写法
+type OfficialRecords = (Vec<String>, Vec<String>, Vec<String>, Vec<String>, Vec<String>);
+
+// 从reader读谱时，报错信息里用这个代替文件路径
+const READER_PATH: &str = "<reader>";
+
 pub enum Section {
     None,
     BpmSection,
+    TimeSignatureSection,
     SpeedSection,
     NoteSection,
 }
 
-pub trait DistanceGetter {
-    fn get_distance(&self) -> f32;
-}
-
-impl DistanceGetter for SpeedDistance {
-    fn get_distance(&self) -> f32 {
-        self.distance
+impl Chart {
+    // data不需要排好序
+    pub fn new(data: ChartData) -> Self {
+        let mut chart: Chart = Self {
+            data,
+            timeline: ChartTimeline::default(),
+            hitsound_list: Vec::new(),
+        };
+        chart.update();
+        chart
     }
-}
 
-impl DistanceGetter for TrailDistance {
-    fn get_distance(&self) -> f32 {
-        self.distance
+    pub fn create_empty_chart() -> Self {
+        Self::new(ChartData::new())
     }
-}
 
-pub trait       TimeGetter {
-    fn get_time(&self) -> f32;
-}
-
-impl TimeGetter for TrailDistance {
-    fn get_time(&self) -> f32 {
-        self.time
+    pub fn data(&self) -> &ChartData {
+        &self.data
     }
-}
 
-impl TimeGetter for SpeedDistance {
-    fn get_time(&self) -> f32 {
-        self.time
+    pub fn timeline(&self) -> &ChartTimeline {
+        &self.timeline
     }
-}
 
-impl TimeGetter for Speed {
-    fn get_time(&self) -> f32 {
-        self.time
+    pub fn into_data(self) -> ChartData {
+        self.data
     }
-}
 
-impl TimeGetter for Note {
-    fn get_time(&self) -> f32 {
-        self.get_time()
+    // 修改谱面，改完之后重新排序并重新生成timeline和打击音队列
+    pub fn edit<F: FnOnce(&mut ChartData)>(&mut self, f: F) {
+        f(&mut self.data);
+        self.update();
     }
-}
 
-impl TimeGetter for BPM {
-    fn get_time(&self) -> f32 {
-        self.time
+    // 和edit一样，但只重新计算from_time之后受影响的部分，编辑器里拖动时用
+    // 只能改动from_time之后的speed、note，见ChartTimeline::rebuild_from
+    // from_time之前的speed或note的数量变了时退回完整的update
+    // 内容有没有改动要复制整个前半部分才能比较，只在debug版本里检查，release版本里改了前半部分的结果不对
+    pub fn edit_from<F: FnOnce(&mut ChartData)>(&mut self, from_time: f64, f: F) {
+        let speed_count: usize = self.data.speed.partition_point(|speed| speed.time < from_time);
+        let note_count: usize = self.data.note.partition_point(|note| note.get_time() < from_time);
+        let before: Option<(Vec<Speed>, Vec<Note>)> = if cfg!(debug_assertions) {
+            Some((self.data.speed[..speed_count].to_vec(), self.data.note[..note_count].to_vec()))
+        } else {
+            None
+        };
+        f(&mut self.data);
+        self.data.sort();
+        let unchanged: bool = self.data.speed.partition_point(|speed| speed.time < from_time) == speed_count
+            && self.data.note.partition_point(|note| note.get_time() < from_time) == note_count
+            && before.is_none_or(|(speed, note)| self.data.speed[..speed_count] == speed[..] && self.data.note[..note_count] == note[..]);
+        if !unchanged {
+            log::debug!("edit_from: data before {} changed, rebuilding the whole timeline", from_time);
+            self.update();
+            return;
+        }
+        let timeline: ChartTimeline = std::mem::take(&mut self.timeline);
+        self.timeline = timeline.rebuild_from(&self.data, from_time);
+        self.update_hitsound();
     }
-}
 
-impl Chart {
-    pub fn create_empty_chart() -> Self {
-        Self {
-            version: 0,
-            offset: 0.0,
-            bpm: Vec::new(),
-            speed: Vec::new(),
-            note: Vec::new(),
-            speed_distance: Vec::new(),
-            speed_distance_plain: Vec::new(),
-            trail_distance: Vec::new(),
-            trail_distance_plain: Vec::new(),
-
-            hitsound_list: Vec::new(),
-
-            phone_trail_distance: Vec::new(),
-            // single_trail_distance: Vec::new(),
-        }
+    pub fn set_data(&mut self, data: ChartData) {
+        self.data = data;
+        self.update();
     }
 
-    pub fn update(&mut self) { // 更新谱面
-        self.sort_chart();
-        self.distance_preprocessing();
-        self.update_plain_chart();
+    fn update(&mut self) { // 更新谱面
+        self.data.sort();
+        self.timeline = ChartTimeline::new(&self.data);
         self.update_hitsound();
     }
 
-    pub fn reset_hitsound(&mut self, chart_time: f32){ // 根据时间重置打击音队列
-        for i in 0..self.hitsound_list.len() {
-            if self.hitsound_list[i].time >= chart_time {
-                self.hitsound_list[i].played = false;
+    pub fn reset_hitsound(&mut self, chart_time: f64){ // 根据时间重置打击音队列
+        for hitsound in self.hitsound_list.iter_mut() {
+            hitsound.played = hitsound.time < chart_time;
+        }
+    }
+
+    // 这个函数的作用是取出到chart_time为止还没播放的打击音，并标记为已播放，按时间先后返回
+    pub fn take_due_hitsounds(&mut self, chart_time: f64) -> Vec<HitSoundKind> {
+        let mut kinds: Vec<HitSoundKind> = Vec::new();
+        for hitsound in self.hitsound_list.iter_mut() {
+            if hitsound.time > chart_time {
+                break;
             }
-            else{
-                self.hitsound_list[i].played = true;
+            if !hitsound.played {
+                hitsound.play();
+                kinds.push(hitsound.kind);
             }
         }
+        kinds
     }
 
     pub fn update_hitsound(&mut self){ // 重新生成打击音队列
         self.hitsound_list.clear();
-        for i in 0..self.note.len() {
-            let note = &self.note[i];
+        for i in 0..self.data.note.len() {
+            let note = &self.data.note[i];
             match note {
                 Note::Tap(tap) => { 
-                    let time = tap.time;
-                    let note_type = 0;
-                    let hitsound = HitSound::new(time, note_type);
+                    let hitsound = HitSound::new(tap.time, HitSoundKind::Tap);
                     self.hitsound_list.push(hitsound);
                 },
                 Note::Flick(flick) => {
-                    let degree = flick.degree;
-                    let time = flick.time;
-                    let note_type = 1;
-                    let hitsound = HitSound::new(time, note_type);
+                    let hitsound = HitSound::new(flick.time, HitSoundKind::Flick);
                     self.hitsound_list.push(hitsound);
                 },
                 Note::Slide(slide) => {
-                    let degree = slide.degree;
-                    let time = slide.time;
-                    let slidetype = slide.slidetype;
-                    if slide.slidetype != 3 {
-                        let hitsound = HitSound::new(time, slidetype);
-                        self.hitsound_list.push(hitsound);
-                    } else {
-                        let hitsound = HitSound::new(time, 2);
-                        self.hitsound_list.push(hitsound);
-                    }
+                    let hitsound = HitSound::new(slide.time, HitSoundKind::from_slide_head(slide.slidetype));
+                    self.hitsound_list.push(hitsound);
 
-                    for j in 1..slide.amount {
-                        // 填4就是4分音符
-                        // let snaptime = 60.0 / self.bpm[0].bpm * 1000.0; // TODO chart get bpm
-                        let snaptime = 60.0 / self.find_bpm_by_time(slide.time) * 1000.0;
-                        let time = slide.time + j as f32 * snaptime / slide.snap as f32;
-                        let hitsound = HitSound::new(time, 2);
+                    for time in slide.tick_times(self) {
+                        let hitsound = HitSound::new(time, HitSoundKind::Slide);
                         self.hitsound_list.push(hitsound);
                     }
                 },
                 Note::Rotate(rotate) => {
-                    let delta = rotate.delta;
-                    let time = rotate.time;
-                    let note_type = if delta < 0.0 { 3 } else { 4 };
-                    let hitsound = HitSound::new(time, note_type);
+                    let kind = if rotate.delta < 0.0 { HitSoundKind::RotateLeft } else { HitSoundKind::RotateRight };
+                    let hitsound = HitSound::new(rotate.time, kind);
                     self.hitsound_list.push(hitsound);
                 },
                 Note::Catch(catch) => {
-                    let time = catch.time;
-                    let note_type = 5;
-                    let hitsound = HitSound::new(time, note_type);
+                    let hitsound = HitSound::new(catch.time, HitSoundKind::Catch);
                     self.hitsound_list.push(hitsound);
-                    // draw_text(&format!("deg: {}", deg), x, y, 20.0, GREEN);
-                },
-                Note::Trail(trail) => {
-                    continue;
                 },
-                Note::Bomb(bomb) => {
+                Note::Trail(_) | Note::Bomb(_) => {
                     continue;
                 },
-                _ => {}
-                
             }
         }
-        self.hitsound_list.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        self.hitsound_list.sort_by(|a, b| a.time.total_cmp(&b.time));
     }
 
-    pub fn update_plain_chart(&mut self){ // 更新没有速度事件的谱面
-        let mut plain_chart = self.clone();
-        plain_chart.speed = vec![Speed::new(0.0, 1.0, 0)];
-        plain_chart.trail_distance = vec![];
-        plain_chart.speed_distance = vec![];
-        plain_chart.distance_preprocessing();
-        self.trail_distance_plain = plain_chart.trail_distance;
-        self.speed_distance_plain = plain_chart.speed_distance;
+    pub fn load_chart_from_official(path: &str) -> Result<Self, ChartError> {
+        Self::load_chart_from_official_with_options(path, &ParseOptions::strict()).map(|(chart, _)| chart)
     }
 
-    pub fn get_plain_chart(&self) -> Chart{ // 返回一个没有速度事件的谱面
-        let mut plain_chart = self.clone();
-        plain_chart.speed = vec![Speed::new(0.0, 1.0, 0)];
-        plain_chart.trail_distance = plain_chart.trail_distance_plain.clone();
-        plain_chart.speed_distance = plain_chart.speed_distance_plain.clone();
-        plain_chart
+    pub fn load_chart_from_official_with_options(path: &str, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
+        log::info!("Loading chart from official format: {}", path);
+        let file: File = File::open(Path::new(path)).map_err(|e| ChartError::io(path, e))?;
+        Self::read_official(io::BufReader::new(file), path, options)
     }
 
-    pub fn load_chart_from_official(path: &str) -> io::Result<Self> {
-        log::info!("Loading chart from official format: {}", path);
-        let file: File = File::open(Path::new(path))?;
-        let reader: io::BufReader<File> = io::BufReader::new(file);
+    pub fn from_official_reader<R: BufRead>(reader: R) -> Result<Self, ChartError> {
+        Self::from_official_reader_with_options(reader, &ParseOptions::strict()).map(|(chart, _)| chart)
+    }
+
+    pub fn from_official_reader_with_options<R: BufRead>(reader: R, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
+        Self::read_official(reader, READER_PATH, options)
+    }
 
+    // path只用于报错
+    fn read_official<R: BufRead>(mut reader: R, path: &str, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
         let mut section: Section = Section::None;
-        let mut chart: Chart = Chart::create_empty_chart();
-
-        for line in reader.lines() {
-            let line: String = line?;
-            match line.as_str() {
-                _ if line.starts_with("# Version") => chart.version = line.split_whitespace().last().unwrap().parse().unwrap(),
-                _ if line.starts_with("# BPM") => section = Section::BpmSection,
-                _ if line.starts_with("# Speed") => section = Section::SpeedSection,
-                _ if line.starts_with("# Note") => section = Section::NoteSection,
-                _ => match section {
-                    Section::BpmSection => {
-                        let parts: Vec<&str> = line.split(',').collect();
-                        if line.is_empty(){
-                            log::warn!("Empty line, Skipping");
-                            continue;
-                        }
-                        if parts.len() != 2 {
-                            log::error!("Invalid BPM line: {}, Skipping", line);
-                            continue;
-                        }
-                        chart.bpm.push(BPM::new(parts[0].parse().unwrap(), parts[1].parse().unwrap()));
-                    }
-                    Section::SpeedSection => {
-                        let parts: Vec<&str> = line.split(',').collect();
-                        if line.is_empty(){
-                            log::warn!("Empty line, Skipping");
-                            continue;
-                        }
-                        if parts.len() != 3 {
-                            log::error!("Invalid Speed line: {}, Skipping", line);
-                            continue;
-                        }
-                        chart.speed.push(Speed::new(parts[0].parse().unwrap(), parts[1].parse().unwrap(), parts[2].parse().unwrap()));
+        let mut data: ChartData = ChartData::new();
+        let mut report: ParseReport = ParseReport::new();
+        let mut buffer: String = String::new();
+        let mut line_no: usize = 0;
+
+        loop {
+            buffer.clear();
+            if reader.read_line(&mut buffer).map_err(|e| ChartError::io(path, e))? == 0 {
+                break;
+            }
+            line_no += 1;
+            // Windows上编辑过的谱面开头可能有BOM，去掉后记下来，导出时写回去
+            if line_no == 1 && buffer.starts_with(BOM) {
+                buffer.drain(..BOM.len_utf8());
+                data.source_format.has_bom = true;
+            }
+            // 每一行的换行符都记下来，导出时原样还原
+            let content: &str = buffer.strip_suffix('\n').unwrap_or(&buffer);
+            let line: &str = content.strip_suffix('\r').unwrap_or(content);
+            let ending: &str = &buffer[line.len()..];
+            match Self::parse_official_line(&mut data, &mut section, path, line_no, line, options, &mut report) {
+                Ok(Some(canonical)) => data.source_format.push_record(canonical, line, ending),
+                Ok(None) => data.source_format.push_text(line, ending),
+                Err(error) if options.strict => return Err(error),
+                Err(error) => {
+                    // 跳过的行不进谱面，但排版里保留，谱面没改过时照样写回
+                    report.skip(error);
+                    data.source_format.push_text(line, ending);
+                },
+            }
+        }
+        data.source_format.finish();
+        report.lints = lint::lint_source_order(&data);
+        Ok((Chart::new(data), report))
+    }
+
+    // 读官谱的一行，出错时这一行不会写入谱面
+    // 读到谱面数据时返回这一行的规范写法，节标题、空行等返回None
+    fn parse_official_line(data: &mut ChartData, section: &mut Section, path: &str, line_no: usize, line: &str, options: &ParseOptions, report: &mut ParseReport) -> Result<Option<String>, ChartError> {
+        // 表头按"# "后面的第一个词精确匹配，"# OffsetFoo"这种不认识的表头当作metadata
+        let key: Option<&str> = line.strip_prefix("# ").and_then(|rest| rest.split_whitespace().next());
+        let canonical: String = match key {
+            Some("Version") => {
+                let fields = LineFields::split_header(path, line_no, line, options);
+                if fields.len() != 3 {
+                    return Err(ChartError::BadSectionHeader { path: path.to_string(), line: line_no, column: 1, header: line.to_string() });
+                }
+                data.version = fields.parse(2)?;
+                fields.finish(report);
+                format!("# Version {}", data.version)
+            },
+            Some("Offset") => {
+                let fields = LineFields::split_header(path, line_no, line, options);
+                if fields.len() != 3 {
+                    return Err(ChartError::BadSectionHeader { path: path.to_string(), line: line_no, column: 1, header: line.to_string() });
+                }
+                data.offset = fields.parse(2)?;
+                fields.finish(report);
+                data.source_format.has_offset = true;
+                format!("# Offset {}", format_number(data.offset, None))
+            },
+            Some("BPM") => {
+                *section = Section::BpmSection;
+                return Ok(None);
+            },
+            Some("TimeSignature") => {
+                *section = Section::TimeSignatureSection;
+                return Ok(None);
+            },
+            Some("Speed") => {
+                *section = Section::SpeedSection;
+                return Ok(None);
+            },
+            Some("Note") => {
+                *section = Section::NoteSection;
+                return Ok(None);
+            },
+            Some("Extension") if line == EXTENSION_HEADER => {
+                data.source_format.has_easing_extension = true;
+                line.to_string()
+            },
+            _ if line.starts_with('#') => {
+                // 不认识的表头原样保存，导出时写回去
+                data.metadata.push(line.to_string());
+                line.to_string()
+            },
+            _ if line.is_empty() => {
+                report.ignore(Diagnostic::new(path, line_no, 1, "empty line".to_string()));
+                return Ok(None);
+            },
+            _ => match section {
+                Section::BpmSection => {
+                    let fields = LineFields::split(path, line_no, line, options);
+                    fields.expect_len("BPM", 2)?;
+                    data.bpm.push(BPM::new(fields.parse(0)?, fields.parse(1)?));
+                    fields.finish(report);
+                    bpm_line(data.bpm.last().unwrap(), None)
+                }
+                Section::TimeSignatureSection => {
+                    let fields = LineFields::split(path, line_no, line, options);
+                    fields.expect_len("TimeSignature", 3)?;
+                    data.time_signature.push(TimeSignature::new(fields.parse(0)?, fields.parse(1)?, fields.parse(2)?));
+                    fields.finish(report);
+                    time_signature_line(data.time_signature.last().unwrap(), None)
+                }
+                Section::SpeedSection => {
+                    let fields = LineFields::split(path, line_no, line, options);
+                    if fields.len() < 3 {
+                        fields.expect_len("Speed", 3)?;
                     }
-                    Section::NoteSection => {
-                        let parts: Vec<&str> = line.split(',').collect();
-                        if line.is_empty() {
-                            log::warn!("Empty line, Skipping");
-                            continue;
-                        }
-                        if parts.len() < 3 {
-                            log::error!("Invalid Note line: {}, Skipping", line);
-                            continue;
+                    let code: i32 = fields.parse(2)?;
+                    let easing: Easing = if !data.source_format.has_easing_extension {
+                        // 官谱格式只认0和1，其他编号和以前一样当作Step
+                        fields.expect_len("Speed", 3)?;
+                        if code == Easing::Linear.code() {
+                            Easing::Linear
+                        } else {
+                            if code != Easing::Step.code() {
+                                report.ignore(Diagnostic::new(path, line_no, fields.column(2), format!("smooth {} read as step, `{}` is needed for extended easings", code, EXTENSION_HEADER)));
+                            }
+                            Easing::Step
                         }
-                        let note_type: i32 = parts[0].parse().unwrap();
-                        let note = match note_type {
-                            0 => {
-                                if parts.len() != 3{log::error!("Invalid Tap note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Tap note");
-                                Note::Tap(Tap::new(parts[1].parse().unwrap(), parts[2].parse().unwrap()))
-                            },
-                            1 => {
-                                if parts.len() != 3{log::error!("Invalid Flick note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Flick note");
-                                Note::Flick(Flick::new(parts[1].parse().unwrap(), parts[2].parse().unwrap()))
-                            },
-                            2 => {
-                                if parts.len() != 9{log::error!("Invalid Slide note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Slide note");
-                                Note::Slide(Slide::new(parts[1].parse().unwrap(), parts[2].parse().unwrap(), parts[3].parse().unwrap(), parts[4].parse().unwrap(), parts[5].parse().unwrap(), parts[6].parse().unwrap(), parts[7].parse().unwrap(), parts[8].parse().unwrap()))
-                            },
-                            4 => {
-                                if parts.len() != 6{log::error!("Invalid Rotate note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Rotate note");
-                                Note::Rotate(Rotate::new(parts[1].parse().unwrap(), parts[2].parse().unwrap(), parts[3].parse().unwrap(), parts[4].parse().unwrap(), parts[5].parse().unwrap()))
-                            },
-                            5 => {
-                                if parts.len() != 3{log::error!("Invalid Catch note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Catch note");
-                                Note::Catch(Catch::new(parts[1].parse().unwrap(), parts[2].parse().unwrap()))
-                            },
-                            6 => {
-                                if parts.len() != 3{log::error!("Invalid Bomb note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Bomb note");
-                                Note::Bomb(Bomb::new(parts[1].parse().unwrap(), parts[2].parse().unwrap()))
+                    } else {
+                        match Easing::from_code(code) {
+                            Some(easing) => {
+                                fields.expect_len("Speed", 3)?;
+                                easing
                             },
-                            11 => {
-                                if parts.len() != 6{log::error!("Invalid Trail note line: {}, Skipping", line); continue;}
-                                // log::info!("Parsing Trail note");
-                                Note::Trail(Trail::new(parts[1].parse().unwrap(), parts[2].parse().unwrap(), parts[3].parse().unwrap(), parts[4].parse().unwrap(), parts[5].parse().unwrap()))
+                            None if code == BEZIER_CODE => {
+                                fields.expect_len("Speed", 7)?;
+                                Easing::Bezier { x1: fields.parse(3)?, y1: fields.parse(4)?, x2: fields.parse(5)?, y2: fields.parse(6)? }
                             },
-                            _ => {
-                                log::warn!("Unknown note type: {}, Skipping", note_type);
-                                continue;
+                            None => {
+                                return Err(ChartError::UnknownEasing { path: path.to_string(), line: line_no, column: fields.column(2), code: code.to_string() });
                             },
-                        };
-                        chart.note.push(note);
+                        }
+                    };
+                    data.speed.push(Speed::new(fields.parse(0)?, fields.parse(1)?, easing));
+                    fields.finish(report);
+                    speed_line(data.speed.last().unwrap(), None)
+                }
+                Section::NoteSection => {
+                    let fields = LineFields::split(path, line_no, line, options);
+                    let note_type: i32 = fields.parse(0)?;
+                    let note = match note_type {
+                        0 => {
+                            fields.expect_len("Tap", 3)?;
+                            Note::Tap(Tap::new(fields.parse(1)?, fields.parse(2)?))
+                        },
+                        1 => {
+                            fields.expect_len("Flick", 3)?;
+                            Note::Flick(Flick::new(fields.parse(1)?, fields.parse(2)?))
+                        },
+                        2 => {
+                            fields.expect_len("Slide", 9)?;
+                            let code: i32 = fields.parse(3)?;
+                            let slidetype: SlideType = SlideType::try_from(code)
+                                .map_err(|_| ChartError::UnknownSlideType { path: path.to_string(), line: line_no, column: fields.column(3), code: code.to_string() })?;
+                            Note::Slide(Slide::new(fields.parse(1)?, fields.parse(2)?, slidetype, fields.parse(4)?, fields.parse(5)?, fields.parse(6)?, fields.parse(7)?, fields.parse(8)?))
+                        },
+                        4 => {
+                            fields.expect_len("Rotate", 6)?;
+                            Note::Rotate(Rotate::new(fields.parse(1)?, fields.parse(2)?, fields.parse(3)?, fields.parse(4)?, fields.parse(5)?))
+                        },
+                        5 => {
+                            fields.expect_len("Catch", 3)?;
+                            Note::Catch(Catch::new(fields.parse(1)?, fields.parse(2)?))
+                        },
+                        6 => {
+                            fields.expect_len("Bomb", 3)?;
+                            Note::Bomb(Bomb::new(fields.parse(1)?, fields.parse(2)?))
+                        },
+                        11 => {
+                            fields.expect_len("Trail", 6)?;
+                            Note::Trail(Trail::new(fields.parse(1)?, fields.parse(2)?, fields.parse(3)?, fields.parse(4)?, fields.parse(5)?))
+                        },
+                        _ => {
+                            return Err(ChartError::UnknownNoteType { path: path.to_string(), line: line_no, column: 1, key: String::new(), note_type: note_type.to_string() });
+                        },
+                    };
+                    let canonical: String = note_line(&note, None);
+                    data.note.push(note);
+                    fields.finish(report);
+                    canonical
+                }
+                Section::None => {
+                    if options.strict {
+                        return Err(ChartError::OutsideSection { path: path.to_string(), line: line_no, column: 1, text: line.to_string() });
                     }
-                    Section::None => (),
+                    report.warn(Diagnostic::new(path, line_no, 1, format!("line outside of any section: `{}`", line)));
+                    return Ok(None);
                 },
-            }
-        }
-        chart.update_hitsound();
-        chart.distance_preprocessing();
-        Ok(chart)
+            },
+        };
+        Ok(Some(canonical))
+    }
+
+    pub fn load_chart_from_json(path: &str) -> Result<Self, ChartError> {
+        Self::load_chart_from_json_with_options(path, &ParseOptions::strict()).map(|(chart, _)| chart)
     }
 
-    pub fn load_chart_from_json(path: &str) -> io::Result<Self> {
+    pub fn load_chart_from_json_with_options(path: &str, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
         log::info!("Loading chart from json format: {}", path);
-        let mut file: File = File::open(path)?;
+        let file: File = File::open(path).map_err(|e| ChartError::io(path, e))?;
+        Self::read_json(io::BufReader::new(file), path, options)
+    }
+
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, ChartError> {
+        Self::from_json_reader_with_options(reader, &ParseOptions::strict()).map(|(chart, _)| chart)
+    }
+
+    pub fn from_json_reader_with_options<R: Read>(reader: R, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
+        Self::read_json(reader, READER_PATH, options)
+    }
+
+    // path只用于报错
+    fn read_json<R: Read>(mut reader: R, path: &str, options: &ParseOptions) -> Result<(Self, ParseReport), ChartError> {
         let mut data: String = String::new();
-        file.read_to_string(&mut data)?;
-        let json: Value = serde_json::from_str(&data)?;
-        let mut chart: Chart = Chart::create_empty_chart();
-        chart.version = json["version"].as_i64().unwrap() as i32;
-        chart.offset = json["offset"].as_f64().unwrap() as f32;
-        for bpm in json["bpm"].as_array().unwrap() {
-            chart.bpm.push(serde_json::from_value(bpm.clone()).unwrap());
+        reader.read_to_string(&mut data).map_err(|e| ChartError::io(path, e))?;
+        let mut json: Value = serde_json::from_str(&data).map_err(|e| json_error(path, "", e))?;
+        let schema_version: i32 = schema::migrate(&mut json, path)?;
+        if schema_version < schema::SCHEMA_VERSION {
+            log::info!("Migrated json chart from schema version {} to {}", schema_version, schema::SCHEMA_VERSION);
+        }
+        let mut report: ParseReport = ParseReport::new();
+        let empty: Vec<Value> = Vec::new();
+
+        // 缺少顶层字段时，宽松模式用默认值代替
+        let missing = |key: &str, report: &mut ParseReport| {
+            if options.strict {
+                return Err(ChartError::MissingJsonKey { path: path.to_string(), key: key.to_string() });
+            }
+            report.recover(Diagnostic::new(path, 0, 0, format!("missing json key `{}`, using default", key)));
+            Ok(())
+        };
+
+        let mut data: ChartData = ChartData::new();
+        match json.get("version").and_then(Value::as_i64) {
+            Some(version) => data.version = version as i32,
+            None => missing("version", &mut report)?,
         }
-        for note in json["note"].as_array().unwrap() {
-            let note_type: i32 = note["type"].as_i64().unwrap() as i32;
-            let note = match note_type {
-                0 => Note::Tap(serde_json::from_value(note.clone()).unwrap()),
-                1 => Note::Flick(serde_json::from_value(note.clone()).unwrap()),
-                2 => Note::Slide(serde_json::from_value(note.clone()).unwrap()),
-                4 => Note::Rotate(serde_json::from_value(note.clone()).unwrap()),
-                5 => Note::Catch(serde_json::from_value(note.clone()).unwrap()),
-                6 => Note::Bomb(serde_json::from_value(note.clone()).unwrap()),
-                11 => Note::Trail(serde_json::from_value(note.clone()).unwrap()),
-                _ => {
-                    log::warn!("Unknown note type: {}, Skipping", note_type);
-                    continue;
-                },
-            };
-            chart.note.push(note);
+        match json.get("offset").and_then(Value::as_f64) {
+            Some(offset) => data.offset = offset,
+            None => missing("offset", &mut report)?,
         }
-        chart.distance_preprocessing();
-        chart.update_hitsound();
-        Ok(chart)
-    }
-
-    // 这个函数的作用是对bpm、speed、note进行排序
-    pub fn sort_chart(&mut self) {
-        self.bpm.sort_by(|a: &BPM, b: &BPM| a.time.partial_cmp(&b.time).unwrap());
-        self.speed.sort_by(|a: &Speed, b: &Speed| a.time.partial_cmp(&b.time).unwrap());
-        self.note.sort_by(|a: &Note, b: &Note| a.get_time().partial_cmp(&b.get_time()).unwrap());
-
-        self.speed_distance.sort_by(|a: &SpeedDistance, b: &SpeedDistance| a.time.partial_cmp(&b.time).unwrap());
-        self.trail_distance.sort_by(|a: &TrailDistance, b: &TrailDistance| {
-            if a.distance < b.distance {
-                std::cmp::Ordering::Less
-            } else if a.distance == b.distance {
-                a.time.partial_cmp(&b.time).unwrap()
-            } else {
-                std::cmp::Ordering::Greater
+        if let Some(metadata) = json.get("metadata").and_then(Value::as_array) {
+            data.metadata = metadata.iter().filter_map(Value::as_str).map(str::to_string).collect();
+        }
+        let bpm_list: &Vec<Value> = match json.get("bpm").and_then(Value::as_array) {
+            Some(list) => list,
+            None => {
+                missing("bpm", &mut report)?;
+                &empty
+            },
+        };
+        let note_list: &Vec<Value> = match json.get("note").and_then(Value::as_array) {
+            Some(list) => list,
+            None => {
+                missing("note", &mut report)?;
+                &empty
+            },
+        };
+
+        for (i, bpm) in bpm_list.iter().enumerate() {
+            match from_json_value(path, &format!("bpm[{}]", i), bpm) {
+                Ok(bpm) => data.bpm.push(bpm),
+                Err(error) if options.strict => return Err(error),
+                Err(error) => report.skip(error),
+            }
+        }
+        // 没有拍号时按4/4拍，不需要警告
+        if let Some(signature_list) = json.get("time_signature").and_then(Value::as_array) {
+            for (i, signature) in signature_list.iter().enumerate() {
+                match from_json_value(path, &format!("time_signature[{}]", i), signature) {
+                    Ok(signature) => data.time_signature.push(signature),
+                    Err(error) if options.strict => return Err(error),
+                    Err(error) => report.skip(error),
+                }
             }
-        });
-
-        self.phone_trail_distance.sort_by(|a: &TrailDistance, b: &TrailDistance| {
-            if a.distance < b.distance {
-                std::cmp::Ordering::Less
-            } else if a.distance == b.distance {
-                a.time.partial_cmp(&b.time).unwrap()
-            } else {
-                std::cmp::Ordering::Greater
+        }
+        // 旧版导出的json没有speed，读进来就是没有变速
+        match json.get("speed").and_then(Value::as_array) {
+            Some(speed_list) => {
+                for (i, speed) in speed_list.iter().enumerate() {
+                    match from_json_value(path, &format!("speed[{}]", i), speed) {
+                        Ok(speed) => data.speed.push(speed),
+                        Err(error) if options.strict => return Err(error),
+                        Err(error) => report.skip(error),
+                    }
+                }
+            },
+            // v1没有speed，v2开始一定有
+            None if schema_version < 2 => {},
+            None => missing("speed", &mut report)?,
+        }
+        for (i, note) in note_list.iter().enumerate() {
+            match Self::parse_json_note(path, i, note) {
+                Ok(note) => data.note.push(note),
+                Err(error) if options.strict => return Err(error),
+                Err(error) => report.skip(error),
             }
-        });
+        }
+        report.lints = lint::lint_source_order(&data);
+        Ok((Chart::new(data), report))
+    }
+
+    fn parse_json_note(path: &str, index: usize, note: &Value) -> Result<Note, ChartError> {
+        let key: String = format!("note[{}]", index);
+        let typename: &str = match note.get("typename").and_then(Value::as_str) {
+            Some(typename) => typename,
+            None => return Err(ChartError::MissingJsonKey { path: path.to_string(), key: format!("{}.typename", key) }),
+        };
+        if !["Tap", "Flick", "Slide", "Rotate", "Catch", "Trail", "Bomb"].contains(&typename) {
+            return Err(ChartError::UnknownNoteType { path: path.to_string(), line: 0, column: 0, key: format!("{}.typename", key), note_type: typename.to_string() });
+        }
+        from_json_value(path, &key, note)
     }
 
     // 这个函数的作用是根据实际时间计算谱面时间
-    pub fn real_time_to_chart_time(&self, real_time: f32) -> f32 {
-        let chart_time: f32 = real_time * 1000.0;
+    pub fn real_time_to_chart_time(&self, real_time: f64) -> f64 {
+        let chart_time: f64 = real_time * 1000.0;
         chart_time
     }
 
     // 这个函数的作用是根据谱面时间计算实际时间
-    pub fn chart_time_to_real_time(&self, chart_time: f32) -> f32 {
-        let real_time: f32 = chart_time / (1000.0);
+    pub fn chart_time_to_real_time(&self, chart_time: f64) -> f64 {
+        let real_time: f64 = chart_time / (1000.0);
         real_time
     }
 
-    // 这个函数的作用是根据实际时间计算小节数
-    pub fn real_time_to_beat(&self, real_time: f32) -> f32 {
-        let chart_time: f32 = self.real_time_to_chart_time(real_time);
+    // 这个函数的作用是根据实际时间计算拍数
+    pub fn real_time_to_beat(&self, real_time: f64) -> f64 {
+        let chart_time: f64 = self.real_time_to_chart_time(real_time);
         self.chart_time_to_beat(chart_time)
     }
     
-    // 这个函数的作用是根据小节数计算实际时间
-    pub fn beat_to_real_time(&self, beat: f32) -> f32 {
-        let chart_time: f32 = self.beat_to_chart_time(beat);
+    // 这个函数的作用是根据拍数计算实际时间
+    pub fn beat_to_real_time(&self, beat: f64) -> f64 {
+        let chart_time: f64 = self.beat_to_chart_time(beat);
         self.chart_time_to_real_time(chart_time)
     }
 
-    // 这个函数的作用是根据谱面时间计算小节数
-    pub fn chart_time_to_beat(&self, chart_time: f32) -> f32 {
-        let mut beat = 0.0;
-        let mut last_time = 0.0;
-        for i in 0..self.bpm.len() - 1 {
-            if chart_time >= self.bpm[i].time && chart_time < self.bpm[i + 1].time {
-                beat += (chart_time - last_time) / 60000.0 * self.bpm[i].bpm;
-                break;
-            }
-            beat += (self.bpm[i + 1].time - last_time) / 60000.0 * self.bpm[i].bpm;
-            last_time = self.bpm[i + 1].time;
-        }
-
-        if chart_time >= last_time {
-            beat += (chart_time - last_time) / 60000.0 * self.bpm.last().unwrap().bpm;
-        }
+    // 这个函数的作用是根据谱面时间计算拍数，规则见timing.rs
+    pub fn chart_time_to_beat(&self, chart_time: f64) -> f64 {
+        self.timeline.chart_time_to_beat(chart_time)
+    }
 
-        beat
+    // 这个函数的作用是根据拍数计算谱面时间
+    pub fn beat_to_chart_time(&self, beats: f64) -> f64 {
+        self.timeline.beat_to_chart_time(beats)
     }
 
-    // 这个函数的作用是根据小节数计算谱面时间
-    pub fn beat_to_chart_time(&self, beats: f32) -> f32 {
-        let mut chart_time = 0.0;
-        let mut last_time = 0.0;
-        for i in 0..self.bpm.len() - 1 {
-            if beats >= (self.bpm[i].time - last_time) / 60000.0 * self.bpm[i].bpm && beats < (self.bpm[i + 1].time - last_time) / 60000.0 * self.bpm[i].bpm {
-                chart_time = last_time + (beats - (self.bpm[i].time - last_time) / 60000.0 * self.bpm[i].bpm) / self.bpm[i].bpm * 60000.0;
-                break;
-            }
-            last_time = self.bpm[i + 1].time;
-        }
+    // 这个函数的作用是根据拍数计算小节数，小数部分是小节内的位置，规则见timing.rs
+    pub fn beat_to_measure(&self, beat: f64) -> f64 {
+        self.timeline.beat_to_measure(beat)
+    }
 
-        if beats >= (self.bpm.last().unwrap().time - last_time) / 60000.0 * self.bpm.last().unwrap().bpm {
-            chart_time = last_time + (beats - (self.bpm.last().unwrap().time - last_time) / 60000.0 * self.bpm.last().unwrap().bpm) / self.bpm.last().unwrap().bpm * 60000.0;
-        }
+    // 这个函数的作用是根据小节数计算拍数
+    pub fn measure_to_beat(&self, measure: f64) -> f64 {
+        self.timeline.measure_to_beat(measure)
+    }
 
-        chart_time
+    // 这个函数的作用是根据谱面时间计算小节数
+    pub fn chart_time_to_measure(&self, chart_time: f64) -> f64 {
+        self.timeline.chart_time_to_measure(chart_time)
     }
 
-    // 这个函数的作用是根据时间找到对应的数组下标，其中mode的值有四种情况
-    // 0: 找到小于等于time的最大值
-    // 1: 找到大于等于time的最小值
-    // 2: 找到小于time的最大值
-    // 3: 找到大于time的最小值
-    pub fn find_pos_by_time<T: TimeGetter>(&self, input: &Vec<T>, time: f32, mode: i32) -> usize {   
-        let (mut start, mut end): (usize, usize) = (0, input.len());
-        let mut i: usize;
+    // 这个函数的作用是根据小节数计算谱面时间
+    pub fn measure_to_chart_time(&self, measure: f64) -> f64 {
+        self.timeline.measure_to_chart_time(measure)
+    }
 
-        if input.is_empty() {
-            return usize::MAX;
-        }
+    // 这个函数的作用是找到谱面时间所在小节一小节有多少拍
+    pub fn find_beats_per_measure_by_time(&self, chart_time: f64) -> f64 {
+        self.timeline.find_beats_per_measure_by_time(chart_time)
+    }
 
-        loop {
-            i = (start + end) / 2;
-            if i >= input.len() - 1 {
-                return input.len() - 1;
-            } else if i == 0 {
-                return 0;
-            } else if input[i].get_time() <= time && input[i + 1].get_time() >= time {
-                break;
-            } else if input[i].get_time() < time {
-                start = i;
-            } else if input[i].get_time() > time {
-                end = i;
-            }
-        }
+    // 这个函数的作用是把谱面时间转成拍数位置，分母不超过max_denominator
+    pub fn chart_time_to_beat_position(&self, chart_time: f64, max_denominator: i32) -> BeatPosition {
+        self.timeline.chart_time_to_beat_position(chart_time, max_denominator)
+    }
 
-        if input[i].get_time() < time && input[i + 1].get_time() == time {
-            i += 1;
-        }
+    // 这个函数的作用是根据拍数位置计算谱面时间
+    pub fn beat_position_to_chart_time(&self, position: &BeatPosition) -> f64 {
+        self.timeline.beat_position_to_chart_time(position)
+    }
 
-        let mut new_time = time;
-        match mode {
-            0 => {
-                new_time = input[i].get_time();
-                while i > 0 && input[i - 1].get_time() == new_time {
-                    i -= 1;
-                }
-            }
-            1 => {
-                new_time = input[i].get_time();
-                while i < input.len() - 1 && input[i + 1].get_time() == new_time {
-                    i += 1;
-                }
-            }
-            2 => {
-                if i < input.len() - 1 && input[i].get_time() != new_time {
-                    new_time = input[i + 1].get_time();
-                }
-                while i > 0 && input[i - 1].get_time() == new_time {
-                    i -= 1;
-                }
-            }
-            3 => {
-                if i < input.len() - 1 && input[i].get_time() != new_time {
-                    new_time = input[i + 1].get_time();
-                }
-                while i < input.len() - 1 && input[i + 1].get_time() == new_time {
-                    i += 1;
-                }
-            }
-            _ => {}
-        }
+    // 这个函数的作用是按当前拍号把拍数位置换算成小节位置
+    pub fn beat_position_to_measure_position(&self, position: &BeatPosition, max_denominator: i32) -> MeasurePosition {
+        self.timeline.beat_position_to_measure_position(position, max_denominator)
+    }
 
-        i
+    // 这个函数的作用是按当前拍号把小节位置换算成拍数位置
+    pub fn measure_position_to_beat_position(&self, position: &MeasurePosition, max_denominator: i32) -> BeatPosition {
+        self.timeline.measure_position_to_beat_position(position, max_denominator)
     }
 
-    // 这个函数的作用是根据时间找到对应的速度，然后计算出积分
-    pub fn find_distance_by_time(&self, time: f32) -> f32 {
-        let (mut p1, mut p2): (usize, usize);
-        let (mut d1, mut t1, mut t2, mut v1, mut v2): (f32, f32, f32, f32, f32);
+    // 这个函数的作用是按当前bpm给所有note算出拍数位置，已有的会被覆盖
+    pub fn compute_note_beats(&mut self, max_denominator: i32) {
+        self.edit(|data| data.compute_note_beats(max_denominator));
+    }
 
-        p1 = self.find_pos_by_time(&self.speed_distance, time, 1);
-        t1 = self.speed_distance[p1].time;
-        v1 = self.speed_distance[p1].speed;
-        d1 = self.speed_distance[p1].distance;
-        p2 = p1 + 1;
+    // 这个函数的作用是按note的拍数位置重新计算time，没有拍数位置的note不动
+    pub fn retime_notes(&mut self) {
+        self.edit(|data| data.retime_notes());
+    }
 
-        if p2 < self.speed_distance.len() && self.speed_distance[p2].smooth == 1 {
-            t2 = self.speed_distance[p2].time;
-            v2 = self.speed_distance[p2].speed;
-            d1 + v1 * (time - t1) + (time - t1).powi(2) * (v2 - v1) / (t2 - t1) / 2.0
-        } else {
-            d1 + (time - t1) * v1
-        }
+    // 这个函数的作用是替换bpm并让note保持在原来的拍上，见ChartData::replace_bpm
+    pub fn replace_bpm(&mut self, bpm: Vec<BPM>, max_denominator: i32) {
+        self.edit(|data| data.replace_bpm(bpm, max_denominator));
     }
 
-    pub fn find_bpm_by_time(&self, chart_time: f32) -> f32 {
-        let index = self.find_pos_by_time(&self.bpm, chart_time, 0);
-        return self.bpm[index].bpm;
+    pub fn find_distance_by_time(&self, time: f64) -> f64 {
+        self.timeline.find_distance_by_time(time)
     }
 
-    // 这个函数的作用是根据积分找到对应的时间 
-    // TODO: 有问题， 速度为负数时会有多个对应时间解 少用这个函数
-    pub fn find_time_by_distance(&self, distance: f32) -> f32 {
-        let (mut p1, mut p2): (usize, usize);
-        let (mut d1, mut d2, mut t1, mut t2, mut v1, mut v2): (f32, f32, f32, f32, f32, f32);
-    
-        p1 = self.find_pos_by_distance(&self.speed_distance, distance, 1);
+    pub fn find_bpm_by_time(&self, chart_time: f64) -> f64 {
+        self.timeline.find_bpm_by_time(chart_time)
+    }
 
-        while p1 < self.speed_distance.len() && self.speed_distance[p1].speed == 0.0 {
-            p1 += 1;
-        }
+    pub fn find_time_by_distance(&self, distance: f64) -> f64 {
+        self.timeline.find_time_by_distance(distance)
+    }
 
-        d1 = self.speed_distance[p1].distance;
-        t1 = self.speed_distance[p1].time;
-        v1 = self.speed_distance[p1].speed;
-        p2 = p1 + 1;
+    pub fn find_times_by_distance(&self, distance: f64) -> Vec<f64> {
+        self.timeline.find_times_by_distance(distance)
+    }
 
-        while p2 < self.speed_distance.len() && self.speed_distance[p2].time == self.speed_distance[p1].time {
-            p2 += 1;  
-        }
-    
-        if p2 < self.speed_distance.len() && self.speed_distance[p2].smooth == 1 {
-            d2 = self.speed_distance[p2].distance;
-            t2 = self.speed_distance[p2].time;
-            v2 = self.speed_distance[p2].speed;
-            t1 + ((distance - d1) / v1) + ((distance - d1) * (v2 - v1) / (d2 - d1) / 2.0)
-        } else {
-            t1 + ((distance - d1) / v1)
+    pub fn find_speed_by_time(&self, time: f64) -> f32 {
+        match find_pos_by_time(&self.data.speed, time, SearchMode::LastAtOrBefore) {
+            Some(index) => self.data.speed[index].speed,
+            None => self.data.speed.first().map_or(1.0, |speed| speed.speed),
         }
     }
 
-    pub fn find_speed_by_time(&self, time: f32) -> f32 {
-        let mut p1: usize = self.find_pos_by_time(&self.speed, time, 1);
-        self.speed[p1].speed
+    pub fn get_y_from_x(&self, start: f32, end: f32, control_a: f32, control_b: f32, x: f32) -> f32 {
+        self.timeline.get_y_from_x(start, end, control_a, control_b, x)
     }
 
-    // 这个函数的作用是根据积分找到对应的数组下标，其中mode的值有四种情况
-    // 0: 找到小于等于pos的最大值
-    // 1: 找到大于等于pos的最小值
-    // 2: 找到小于pos的最大值
-    // 3: 找到大于pos的最小值
-    pub fn find_pos_by_distance<T: DistanceGetter>(&self, input: &Vec<T>, pos: f32, mode: i32) -> usize {
-        let mut start: usize = 0;
-        let mut end: usize = input.len();
-        let mut i: usize;
-        if input.is_empty() {
-            return usize::MAX;
-        }
-        loop {
-            i = (start + end) / 2;
-            if i >= input.len() - 1 {
-                return input.len() - 1;
-            } else if i == 0 {
-                return 0;
-            } else if input[i].get_distance() <= pos && input[i + 1].get_distance() >= pos {
-                break;
-            } else if input[i].get_distance() < pos {
-                start = i;
-            } else if input[i].get_distance() > pos {
-                end = i;
-            }
-        }
-    
-        if input[i].get_distance() < pos && input[i + 1].get_distance() == pos {
-            i += 1;
-        }
-    
-        let mut new_time = pos;
-        match mode {
-            0 => {
-                new_time = input[i].get_distance();
-                while i > 0 && input[i - 1].get_distance() == new_time {
-                    i -= 1;
-                }
-            }
-            1 => {
-                new_time = input[i].get_distance();
-                while i < input.len() - 1 && input[i + 1].get_distance() == new_time {
-                    i += 1;
-                }
-            }
-            2 => {
-                if i < input.len() - 1 && input[i].get_distance() != new_time {
-                    new_time = input[i + 1].get_distance();
-                }
-                while i > 0 && input[i - 1].get_distance() == new_time {
-                    i -= 1;
-                }
-            }
-            3 => {
-                if i < input.len() - 1 && input[i].get_distance() != new_time {
-                    new_time = input[i + 1].get_distance();
-                }
-                while i < input.len() - 1 && input[i + 1].get_distance() == new_time {
-                    i += 1;
-                }
-            }
-            _ => {}
-        }
-    
-        i
-    }
-
-    // 这个函数的作用是先计算谱面速度对时间的积分，然后再计算每个trail的位置
-    pub fn distance_preprocessing(&mut self){     
-        let mut time_start: f32 = 0.0;
-        let mut time_end: f32 = 0.0;
-        let mut speed_start: f32 = 0.0;
-        let mut speed_end: f32 = 0.0;
-        let mut smooth: i32 = 0;
-        let mut distance_start: f32 = 0.0;
-
-        let cal  = |speed_start: f32, speed_end: f32, time_start: f32, time_end: f32, smooth: i32| {
-            if smooth == 1 {
-                (speed_start + speed_end) * (time_end - time_start) / 2.0
-            } else {
-                speed_start * (time_end - time_start)
-            }
-        };
+    pub fn find_degree_by_2_trails(&self, trail1: TrailDistance, trail2: TrailDistance, progress: f32) -> f32 {
+        self.timeline.find_degree_by_2_trails(trail1, trail2, progress)
+    }
 
-        if !self.speed.is_empty() {
-            for i in 0..self.speed.len() {
-                time_end = self.speed[i].time;
-                speed_end = self.speed[i].speed;
-                smooth = self.speed[i].smooth;
-
-                if i == 0 {
-                    time_start = 0.0;
-                    if time_end != 0.0 {
-                        if smooth == 1 {
-                            speed_start = speed_end;
-                            smooth = 0;
-                        } else {
-                            speed_start = 1.0;
-                        }
-                        let speed_distance: SpeedDistance = speeddistance::SpeedDistance::new(0.0, speed_start, 0, 0.0);
-                        self.speed_distance.push(speed_distance);
-                    }
-                } else {
-                    time_start = self.speed[i - 1].time;
-                    speed_start = self.speed[i - 1].speed;
-                }
+    pub fn find_degree_by_time(&self, time: f64) -> f32 {
+        self.timeline.find_degree_by_time(time)
+    }
 
-                distance_start += cal(speed_start, speed_end, time_start, time_end, smooth);
-                self.speed_distance.push(speeddistance::SpeedDistance::new(time_end, speed_end, smooth, distance_start));
-            }
-        } else {
-            self.speed_distance.push(speeddistance::SpeedDistance::new(0.0, 1.0, 0, 0.0));
-        }
+    pub fn find_phone_degree_by_time(&self, time: f64) -> f32 {
+        self.timeline.find_phone_degree_by_time(time)
+    }
 
+    pub fn find_degree_by_distance(&self, distance: f64) -> f32 {
+        self.timeline.find_degree_by_distance(distance)
+    }
 
-        for i in 0..self.note.len(){
-            //如果是Trail类型的note就处理trail_distance
-            if let Note::Trail(trail) = &self.note[i]{
-                let distance: f32 = self.find_distance_by_time(trail.time);
-                let trail_distance: TrailDistance = TrailDistance::new(trail.time, trail.degree, trail.delta, trail.prev_curv, trail.next_curv, distance);
-                self.trail_distance.push(trail_distance);
-                continue;
-            }
+    // 这个函数的作用是找到时间和[start, end]有重叠的note，slide按整个持续时间算，按时间排序
+    pub fn notes_in_time_range(&self, start: f64, end: f64) -> impl Iterator<Item = &Note> {
+        self.timeline.note_indices_in_time_range(&self.data.note, start, end).map(move |i| &self.data.note[i])
+    }
 
-            if let Note::Rotate(rotate) = &self.note[i]{
-                let distance: f32 = self.find_distance_by_time(rotate.time);
-                let trail_distance: TrailDistance = TrailDistance::new(rotate.time, rotate.degree, rotate.delta, rotate.prev_curv, rotate.next_curv, distance);
-                self.trail_distance.push(trail_distance);
-                continue;
-            }
+    // 这个函数的作用是找到距离在[start, end]内的note，slide只要有一部分在里面就算，按时间排序
+    // 倒流时一个距离范围对应好几个时间段，在每一段里分别找
+    pub fn notes_in_distance_range(&self, start: f64, end: f64) -> impl Iterator<Item = &Note> {
+        let mut indices: Vec<usize> = self.timeline.time_ranges_by_distance(start, end)
+            .into_iter()
+            .flat_map(|(a, b)| self.timeline.note_indices_in_time_range(&self.data.note, a, b))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices.into_iter().map(move |i| &self.data.note[i])
+    }
 
-        }
+    pub fn export_to_txt(&self, path: &str) -> IoResult<()>{
+        log::info!("Exporting chart to txt format: {}", path);
+        let file: File = File::create(path)?;
+        let mut writer: io::BufWriter<File> = io::BufWriter::new(file);
+        self.write_official(&mut writer)?;
+        writer.flush()
+    }
 
-        if self.trail_distance.is_empty() {
-            self.trail_distance.push(TrailDistance::new(0.0, 90.0, 0.0, 0.0, 0.0, 0.0));
-        }
+    pub fn write_official<W: Write>(&self, file: W) -> IoResult<()> {
+        self.write_official_with_options(file, &WriteOptions::default())
+    }
 
-        if self.trail_distance[0].time != 0.0 {
-            let start = self.trail_distance.first().unwrap();
-            let trail_distance: TrailDistance = TrailDistance::new(0.0, start.degree, 0.0, start.prev_curv, start.next_curv, 0.0);
-            self.trail_distance.insert(0, trail_distance);
+    pub fn write_official_with_options<W: Write>(&self, mut file: W, options: &WriteOptions) -> IoResult<()> {
+        let source: &SourceFormat = &self.data.source_format;
+        let ending: &str = &source.line_ending;
+        let preserve: bool = options.preserve_source && options.precision.is_none();
+        if source.has_bom {
+            write!(file, "{}", BOM)?;
         }
 
-        let last = self.trail_distance.last().unwrap();
-        let distance: f32 = self.find_distance_by_time(last.time + 50000.0);
-        let trail_distance: TrailDistance = TrailDistance::new(last.time + 50000.0, last.degree + last.delta, 0.0, last.prev_curv, last.next_curv, distance);
-        self.trail_distance.push(trail_distance);
-
-        //计算手机怎么转 还有点瑕疵
-        let mut flag: bool = false;
-        for i in 0..self.note.len(){
-            flag = false;
-            let note = &self.note[i];
-            let time = note.get_time();
-            for j in 0..3{
-                if i + j >= self.note.len(){
-                    break;
-                }
-                let temp = &self.note[j + i];
-                if temp.get_time() - time > 100.0{
-                    break;
+        // 谱面没改过就按读入时的排版原样输出
+        let (header, bpm, time_signature, speed, note) = self.official_records(None);
+        if preserve && !source.is_empty() {
+            let body: Vec<String> = [bpm.clone(), time_signature.clone(), speed.clone(), note.clone()].concat();
+            if let Some(layout) = source.layout_for(&header, &body) {
+                for (line, ending) in layout {
+                    write!(file, "{}{}", line, ending)?;
                 }
-                if let Note::Catch(catch) = temp{
-                    let distance: f32 = self.find_distance_by_time(catch.time);
-                    let degree = self.find_degree_by_time(catch.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(catch.time, degree, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                    flag = true;
-                    break;
-                }
-            }
-            if flag{
-                continue;
-            }
-            match note{
-                Note::Catch(catch) => {
-                    let distance: f32 = self.find_distance_by_time(catch.time);
-                    let degree = self.find_degree_by_time(catch.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(catch.time, degree, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                }
-                Note::Tap(tap) => {
-                    let distance: f32 = self.find_distance_by_time(tap.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(tap.time, tap.degree % 180.0, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                }
-                Note::Flick(flick) => {
-                    let distance: f32 = self.find_distance_by_time(flick.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(flick.time, flick.degree % 180.0, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                }
-                Note::Slide(slide) => {
-                    let distance: f32 = self.find_distance_by_time(slide.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(slide.time, slide.degree % 180.0, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                }
-                Note::Bomb(bomb) => {
-                    let distance: f32 = self.find_distance_by_time(bomb.time);
-                    let degree = self.find_degree_by_time(bomb.time);
-                    let trail_distance: TrailDistance = TrailDistance::new(bomb.time, degree % 180.0, 0.0, 0.0, 0.0, distance);
-                    self.phone_trail_distance.push(trail_distance);
-                }
-                Note::Rotate(rotate) => {
-                    let distance1: f32 = self.find_distance_by_time(rotate.time - 30.0);
-                    let distancemid: f32 = self.find_distance_by_time(rotate.time);
-                    let distance2: f32 = self.find_distance_by_time(rotate.time + 30.0);   
-                    let trail_distance1: TrailDistance = TrailDistance::new(rotate.time - 30.0, rotate.degree % 180.0, 0.0, rotate.prev_curv, rotate.next_curv, distance1);
-                    self.phone_trail_distance.push(trail_distance1);
-                    let trail_distance_mid: TrailDistance = TrailDistance::new(rotate.time, rotate.degree % 180.0 + rotate.delta / 2.0, 0.0, rotate.prev_curv, rotate.next_curv, distancemid);
-                    self.phone_trail_distance.push(trail_distance_mid);
-                    let traildistance2: TrailDistance = TrailDistance::new(rotate.time + 30.0, rotate.degree % 180.0 + rotate.delta, 0.0, rotate.prev_curv, rotate.next_curv, distance2);
-                    self.phone_trail_distance.push(traildistance2);
-                }
-                _ => {}
+                return Ok(());
             }
         }
-        let trail_distance: TrailDistance = TrailDistance::new(0.0, 90.0, 0.0, 0.0,0.0, 0.0);
-        self.phone_trail_distance.insert(0, trail_distance);
-
-        let last = self.phone_trail_distance.last().unwrap();
-        let distance: f32 = self.find_distance_by_time(last.time + 200.0);
-        let trail_distance: TrailDistance = TrailDistance::new(last.time + 200.0, 90.0, 0.0, last.prev_curv, last.next_curv, distance);
-        self.phone_trail_distance.push(trail_distance);
-
-        self.sort_chart();
-    }
 
-    pub fn get_y_from_x(&self, start: f32, end: f32, control_a: f32, control_b: f32, x: f32) -> f32 {
-        // find cubic x value based on control point a and b.
-        let calculate_x = |a: f32, b: f32, time: f32| {
-            time * (3.0 * a - 6.0 * a * time + 3.0 * b * time + time * time + 3.0 * a * time * time - 3.0 * b * time * time)
-        };
-    
-        // find cubic y value based on t
-        let calculate_y = |time: f32| {
-            -2.0 * time * time * time + 3.0 * time * time
+        // 否则按默认排版输出，没改过的行仍然用原文
+        let (header, bpm, time_signature, speed, note) = match options.precision {
+            None => (header, bpm, time_signature, speed, note),
+            Some(_) => self.official_records(options.precision),
         };
-    
-        // Approximated t value based on x
-        // binary search
-        let mut upper_time: f32 = 1.0; // tail 
-        let mut lower_time: f32 = 0.0; // head
-        let mut time: f32 = 0.0; // compare time
-    
-        // Do a binary search 10 time to approximate the target time
-        // For each loop, the compare time = (head + tail) / 2
-        // And then compare the X at compare time
-        for _ in 0..20 {
-            time = (upper_time + lower_time) / 2.0;
-            let search_x: f32 = calculate_x(control_a, 1.0 - control_b, time);
-            if search_x < x {
-                lower_time = time;
-            } else {
-                upper_time = time;
-            }
-        }
-    
-        calculate_y(time) * (end - start) + start
-    }
-
-    pub fn find_degree_by_2_trails(&self, mut trail1: TrailDistance, mut trail2: TrailDistance, progress: f32) -> f32 {
-        let calculate_delta = |degree1: f32, degree2: f32| {
-            let mut delta = (degree1 - degree2).abs() % 360.0;
-            if delta > 180.0 {
-                delta = 360.0 - delta;
-            }
-            delta
+        let mut sections: Vec<(&str, Vec<String>)> = vec![("# BPM", bpm)];
+        // 拍号是扩展的节，没有拍号时不输出，保证和官谱格式一致
+        if !time_signature.is_empty() {
+            sections.push(("# TimeSignature", time_signature));
+        }
+        sections.push(("# Speed", speed));
+        sections.push(("# Note", note));
+        let mut raws: RawLines = source.raw_lines();
+        let mut write_record = |file: &mut W, record: &String| -> IoResult<()> {
+            let text: &str = if preserve { raws.take(record) } else { record };
+            write!(file, "{}{}", text, ending)
         };
-
-        let mut time1: f32;
-        let mut time2: f32;
-        let mut degree1: f32;
-        let mut degree2: f32;
-        let mut curvature1: f32;
-        let mut curvature2: f32;
-        let mut result: f32;
-        // 聊天记录：这个我知道是什么原因了 应该是if abs(正常点-上一个点) < abs(对称点-上一个点)  走正常点 else 走对称点
-        // 首先，找到两个trail相距角度最小的一边
-        degree1 = trail1.degree + trail1.delta;
-        degree2 = trail2.degree;
-        if calculate_delta(degree2, degree1) < calculate_delta(degree2 + 180.0, degree1){
-            degree2 += 180.0;
-            degree2 = degree2 % 360.0;
-        }
-        time1 = trail1.time;
-        time2 = trail2.time;
-        curvature1 = trail1.next_curv;
-        curvature2 = trail2.prev_curv;
-        // 获取基础信息
-        // 处理角度
-        degree1 = degree1 % 360.0;
-        if degree1 < 0.0 {
-            degree1 += 360.0;
+        for record in &header {
+            write_record(&mut file, record)?;
         }
-        degree2 = degree2 % 360.0;
-        if degree2 < 0.0 {
-            degree2 += 360.0;
-        }
-
-        if degree1 >= 180.0 {
-            if (degree1 - (degree2 + 360.0)).abs() <= 90.0 {
-                degree2 += 360.0;
-            } else if (degree1 - (degree2 + 180.0)).abs() <= 90.0 {
-                degree2 += 180.0;
-            } else if (degree1 - (degree2 - 180.0)).abs() <= 90.0 {
-                degree2 -= 180.0;
+        for (title, records) in &sections {
+            write!(file, "{}{}{}", ending, title, ending)?;
+            for record in records {
+                write_record(&mut file, record)?;
             }
-        } else {
-            if (degree1 - (degree2 - 360.0)).abs() < 90.0 {
-                degree2 -= 360.0;
-            } else if (degree1 - (degree2 - 180.0)).abs() < 90.0 {
-                degree2 -= 180.0;
-            } else if (degree1 - (degree2 + 180.0)).abs() < 90.0 {
-                degree2 += 180.0;
-            }
-        }
-
-        result = self.get_y_from_x(degree1, degree2, curvature1 / 100.0, curvature2 / 100.0, progress);
-        return result;
-    }
-
-    pub fn find_degree_by_time(&self, time: f32) -> f32 {
-        let mut position1: usize;
-        let mut position2: usize;
-        let mut time1: f32;
-        let mut time2: f32;
-        let mut distance1: f32;
-        let mut distance: f32;
-        let mut distance2: f32;
-        let mut degree1: f32;
-        let mut degree2: f32;
-        let mut curvature1: f32;
-        let mut curvature2: f32;
-        let mut result: f32;
-        distance = self.find_distance_by_time(time);
-        position1 = self.find_pos_by_distance(&self.trail_distance, distance, 1);
-        position2 = position1 + 1;
-        
-        time1 = self.trail_distance[position1].time;
-        if time <= time1{
-            return self.trail_distance[position1].degree;
-        }
-        distance1 = self.trail_distance[position1].distance;
-        degree1 = self.trail_distance[position1].degree + self.trail_distance[position1].delta;
-        curvature1 = self.trail_distance[position1].next_curv;
-
-        if position2 < self.trail_distance.len() {
-            time2 = self.trail_distance[position2].time;
-            distance2 = self.trail_distance[position2].distance;
-            degree2 = self.trail_distance[position2].degree;
-            curvature2 = self.trail_distance[position2].prev_curv;
-            
-            degree1 = degree1 % 360.0;
-            if degree1 < 0.0 {
-                degree1 += 360.0;
-            }
-            degree2 = degree2 % 360.0;
-            if degree2 < 0.0 {
-                degree2 += 360.0;
-            }
-
-            if degree1 >= 180.0 {
-                if (degree1 - (degree2 + 360.0)).abs() <= 90.0 {
-                    degree2 += 360.0;
-                } else if (degree1 - (degree2 + 180.0)).abs() <= 90.0 {
-                    degree2 += 180.0;
-                } else if (degree1 - (degree2 - 180.0)).abs() <= 90.0 {
-                    degree2 -= 180.0;
-                }
-            } else {
-                if (degree1 - (degree2 - 360.0)).abs() < 90.0 {
-                    degree2 -= 360.0;
-                } else if (degree1 - (degree2 - 180.0)).abs() < 90.0 {
-                    degree2 -= 180.0;
-                } else if (degree1 - (degree2 + 180.0)).abs() < 90.0 {
-                    degree2 += 180.0;
-                }
-            }
-
-            result = self.get_y_from_x(degree1, degree2, curvature1 / 100.0, curvature2 / 100.0, (distance - distance1) / (distance2 - distance1));
-            // result = result % 180.0;
-        } else {
-            result = degree1;
-        }
-        if result < 0.0 {
-            result += 360.0;
-        }
-        result = result % 180.0;
-        result
-    }
-
-    pub fn find_phone_degree_by_time(&self, time: f32) -> f32 {
-        let mut position1: usize;
-        let mut position2: usize;
-        let mut time1: f32;
-        let mut time2: f32;
-        let mut distance1: f32;
-        let mut distance: f32;
-        let mut distance2: f32;
-        let mut degree1: f32;
-        let mut degree2: f32;
-        let mut curvature1: f32;
-        let mut curvature2: f32;
-        let mut result: f32;
-        distance = self.find_distance_by_time(time);
-        position1 = self.find_pos_by_distance(&self.phone_trail_distance, distance, 1);
-        position2 = position1 + 1;
-        
-        time1 = self.phone_trail_distance[position1].time;
-        if time <= time1{
-            return self.phone_trail_distance[position1].degree;
-        }
-        distance1 = self.phone_trail_distance[position1].distance;
-        degree1 = self.phone_trail_distance[position1].degree + self.phone_trail_distance[position1].delta;
-        curvature1 = self.phone_trail_distance[position1].next_curv;
-
-        if position2 < self.phone_trail_distance.len() {
-            time2 = self.phone_trail_distance[position2].time;
-            distance2 = self.phone_trail_distance[position2].distance;
-            degree2 = self.phone_trail_distance[position2].degree;
-            curvature2 = self.phone_trail_distance[position2].prev_curv;
-            
-            degree1 = degree1 % 360.0;
-            if degree1 < 0.0 {
-                degree1 += 360.0;
-            }
-            degree2 = degree2 % 360.0;
-            if degree2 < 0.0 {
-                degree2 += 360.0;
-            }
-
-            if degree1 >= 180.0 {
-                if (degree1 - (degree2 + 360.0)).abs() <= 90.0 {
-                    degree2 += 360.0;
-                } else if (degree1 - (degree2 + 180.0)).abs() <= 90.0 {
-                    degree2 += 180.0;
-                } else if (degree1 - (degree2 - 180.0)).abs() <= 90.0 {
-                    degree2 -= 180.0;
-                }
-            } else {
-                if (degree1 - (degree2 - 360.0)).abs() < 90.0 {
-                    degree2 -= 360.0;
-                } else if (degree1 - (degree2 - 180.0)).abs() < 90.0 {
-                    degree2 -= 180.0;
-                } else if (degree1 - (degree2 + 180.0)).abs() < 90.0 {
-                    degree2 += 180.0;
-                }
-            }
-
-            result = self.get_y_from_x(degree1, degree2, curvature1 / 100.0, curvature2 / 100.0, (distance - distance1) / (distance2 - distance1));
-            // result = result % 180.0;
-        } else {
-            result = degree1;
         }
-        if result < 0.0 {
-            result += 360.0;
-        }
-        result = result % 180.0;
-        result
-    }
-
-    pub fn find_degree_by_distance(&self, distance: f32) -> f32 {
-        let time: f32 = self.find_time_by_distance(distance);
-        self.find_degree_by_time(time)
+        Ok(())
     }
 
-    pub fn export_to_txt(&self, path: &str) -> IoResult<()>{
-        log::info!("Exporting chart to txt format: {}", path);
-        let mut file: File = File::create(path)?;
-        writeln!(file, "# Version {}", self.version)?;
-        writeln!(file, "\n# BPM")?;
-        for bpm in &self.bpm {
-            writeln!(file, "{},{}", bpm.time, bpm.bpm)?;
-        }
-        writeln!(file, "\n# Speed")?;
-        for speed in &self.speed {
-            writeln!(file, "{},{},{}", speed.time, speed.speed, speed.smooth)?;
+    // 按导出顺序生成(表头, BPM, TimeSignature, Speed, Note)每一行的写法
+    fn official_records(&self, precision: Option<usize>) -> OfficialRecords {
+        let mut header: Vec<String> = vec![format!("# Version {}", self.data.version)];
+        if self.data.offset != 0.0 || self.data.source_format.has_offset {
+            header.push(format!("# Offset {}", format_number(self.data.offset, precision)));
         }
-        writeln!(file, "\n# Note")?;
-        for note in &self.note {
-           match note {
-               Note::Tap(tap) => {
-                   writeln!(file, "0,{},{}", tap.time, tap.degree)?;
-               },
-               Note::Flick(flick) => {
-                   writeln!(file, "1,{},{}", flick.time, flick.degree)?;
-               },
-               Note::Slide(slide) => {
-                   writeln!(file, "2,{},{},{},{},{},{},{},{}", slide.time, slide.degree, slide.slidetype, slide.end_degree, slide.snap, slide.amount, slide.prev_curv, slide.next_curv)?;
-               },
-               Note::Rotate(rotate) => {
-                   writeln!(file, "4,{},{},{},{},{}", rotate.time, rotate.degree, rotate.delta, rotate.prev_curv, rotate.next_curv)?;
-               },
-               Note::Catch(catch) => {
-                   writeln!(file, "5,{},{}", catch.time, catch.degree)?;
-               },
-               Note::Trail(trail) => {
-                   writeln!(file, "11,{},{},{},{},{}", trail.time, trail.degree, trail.delta, trail.prev_curv, trail.next_curv)?;
-               },
-               Note::Bomb(bomb) => {
-                   writeln!(file, "6,{},{}", bomb.time, bomb.degree)?;
-               },
-               _ => {}
-           }
+        if self.data.speed.iter().any(|speed| !speed.easing.is_official()) || self.data.source_format.has_easing_extension {
+            header.push(EXTENSION_HEADER.to_string());
         }
-        Ok(())
+        header.extend(self.data.metadata.iter().cloned());
+        let bpm: Vec<String> = self.data.bpm.iter().map(|bpm| bpm_line(bpm, precision)).collect();
+        let time_signature: Vec<String> = self.data.time_signature.iter().map(|signature| time_signature_line(signature, precision)).collect();
+        let speed: Vec<String> = self.data.speed.iter().map(|speed| speed_line(speed, precision)).collect();
+        let note: Vec<String> = self.data.note.iter().map(|note| note_line(note, precision)).collect();
+        (header, bpm, time_signature, speed, note)
     }
 
     pub fn export_to_json(&self, path: &str) -> IoResult<()> {
         log::info!("Exporting chart to json format: {}", path);
-        let mut bpm: Vec<Value> = Vec::new();
-        for b in &self.bpm {
-            bpm.push(serde_json::to_value(b).unwrap());
-        }
-        let mut speeddistance: Vec<Value> = Vec::new();
-        for s in &self.speed_distance {
-            speeddistance.push(serde_json::to_value(s).unwrap());
-        }
-        let mut traildistance: Vec<Value> = Vec::new();
-        for t in &self.trail_distance {
-            traildistance.push(serde_json::to_value(t).unwrap());
-        }
-
-        let mut note: Vec<Value> = Vec::new();
-        for n in &self.note {
-            // 根据不同的note类型，将note转换为json
-            match n {
-                Note::Tap(tap) => {
-                    let dict: Value = json!({
-                        "type": 0,
-                        "typename": "Tap",
-                        "time": tap.time,
-                        "degree": tap.degree,
-                    });
-                    note.push(dict);
-                },
-                Note::Flick(flick) => {
-                    let dict: Value = json!({
-                        "type": 1,
-                        "typename": "Flick",
-                        "time": flick.time,
-                        "degree": flick.degree,
-                    });
-                    note.push(dict);
-                },
-                Note::Slide(slide) => {
-                    let dict: Value = json!({
-                        "type": 2,
-                        "typename": "Slide",
-                        "slidetype": slide.slidetype,   
-                        "time": slide.time,
-                        "degree": slide.degree,
-                        "end_degree": slide.end_degree,
-                        "snap": slide.snap,
-                        "amount": slide.amount,
-                        "prev_curv": slide.prev_curv,
-                        "next_curv": slide.next_curv,
-                    });
-                    note.push(dict);
-                },
-                Note::Rotate(rotate) => {
-                    let dict: Value = json!({
-                        "type": 4,
-                        "typename": "Rotate",
-                        "time": rotate.time,
-                        "degree": rotate.degree,
-                        "delta": rotate.delta,
-                        "prev_curv": rotate.prev_curv,
-                        "next_curv": rotate.next_curv,
-                    });
-                    note.push(dict);
-                },
-                Note::Catch(catch) => {
-                    let dict: Value = json!({
-                        "type": 5,
-                        "typename": "Catch",
-                        "time": catch.time,
-                        "degree": catch.degree,
-                        "truedegree": self.find_degree_by_time(catch.time),
-                    });
-                    // print!("Catch: {:?} Time: {:?}\n", dict.get("truedegree").unwrap(), catch.time);
-                    note.push(dict);
-                },
-                Note::Trail(trail) => {
-                    let dict: Value = json!({
-                        "type": 11,
-                        "typename": "Trail",
-                        "time": trail.time,
-                        "degree": trail.degree,
-                        "delta": trail.delta,
-                        "prev_curv": trail.prev_curv,
-                        "next_curv": trail.next_curv,
-                    });
-                    note.push(dict);
-                },
-                Note::Bomb(bomb) => {
-                    let dict: Value = json!({
-                        "type": 6,
-                        "typename": "Bomb",
-                        "time": bomb.time,
-                        "degree": bomb.degree,
-                    });
-                    note.push(dict);
-                },
-                _ => {}
-                
-            }
-        }
-        
-        // let mut deg: Vec<f32> = Vec::new();
-        // for i in 0..100000 {
-        //     deg.push(self.find_degree_by_time(i as f32));
-        // }
-        let json: Value = json!({
-            "version": self.version,
-            "offset": self.offset,
-            "bpm": bpm,
-            "speeddistance": speeddistance,
-            "traildistance": traildistance,
-            "note": note,
-            // "deg": deg,
-        });
+        let file: File = File::create(path)?;
+        let mut writer: io::BufWriter<File> = io::BufWriter::new(file);
+        self.write_json(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_json<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        let mut note: Vec<Note> = self.data.note.clone();
+        for n in note.iter_mut() {
+            if let Note::Catch(catch) = n {
+                catch.truedegree = Some(self.find_degree_by_time(catch.time));
+            }
+        }
+        let json: ChartJson = ChartJson {
+            schema_version: schema::SCHEMA_VERSION,
+            version: self.data.version,
+            offset: self.data.offset,
+            metadata: self.data.metadata.clone(),
+            bpm: self.data.bpm.clone(),
+            time_signature: self.data.time_signature.clone(),
+            speed: self.data.speed.clone(),
+            note,
+            speeddistance: self.timeline.speed_distance().to_vec(),
+            traildistance: self.timeline.trail_distance().to_vec(),
+        };
     
-        let mut json_str: Vec<u8> = Vec::new();
         let formatter: PrettyFormatter = PrettyFormatter::with_indent(b"    ");
-        let mut serializer: Serializer<&mut Vec<u8>, PrettyFormatter> = Serializer::with_formatter(&mut json_str, formatter);
-        json.serialize(&mut serializer).unwrap();
-    
-        let mut file: File = File::create(path)?;
-        file.write_all(&json_str)?;
-    
+        let mut serializer: Serializer<&mut W, PrettyFormatter> = Serializer::with_formatter(&mut writer, formatter);
+        json.serialize(&mut serializer).map_err(io::Error::from)?;
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+// 官谱格式的文本，严格模式读取，需要宽松读取时用from_official_reader_with_options
+impl FromStr for Chart {
+    type Err = ChartError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Chart::from_official_reader(s.as_bytes())
+    }
+}
+
+// 输出官谱格式的文本
+impl fmt::Display for Chart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer: Vec<u8> = Vec::new();
+        self.write_official(&mut buffer).map_err(|_| fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart() -> Chart {
+        let mut data: ChartData = ChartData::new();
+        data.note = vec![
+            Note::Catch(Catch::new(300.0, 0.0)),
+            Note::Tap(Tap::new(100.0, 0.0)),
+            Note::Rotate(Rotate::new(200.0, 0.0, -90.0, 0.0, 0.0)),
+            Note::Flick(Flick::new(200.0, 0.0)),
+            Note::Bomb(Bomb::new(250.0, 0.0)),
+            Note::Trail(Trail::new(250.0, 0.0, 0.0, 0.0, 0.0)),
+        ];
+        Chart::new(data)
+    }
+
+    #[test]
+    fn due_hitsounds_play_once_in_order() {
+        let mut chart: Chart = chart();
+        assert!(chart.take_due_hitsounds(50.0).is_empty());
+        assert_eq!(chart.take_due_hitsounds(200.0), vec![HitSoundKind::Tap, HitSoundKind::RotateLeft, HitSoundKind::Flick]);
+        assert!(chart.take_due_hitsounds(299.0).is_empty());
+        assert_eq!(chart.take_due_hitsounds(1000.0), vec![HitSoundKind::Catch]);
+        assert!(chart.take_due_hitsounds(1000.0).is_empty());
+    }
+
+    #[test]
+    fn reset_replays_later_hitsounds() {
+        let mut chart: Chart = chart();
+        chart.take_due_hitsounds(1000.0);
+        chart.reset_hitsound(150.0);
+        assert_eq!(chart.take_due_hitsounds(1000.0), vec![HitSoundKind::RotateLeft, HitSoundKind::Flick, HitSoundKind::Catch]);
+    }
+}
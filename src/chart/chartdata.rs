@@ -0,0 +1,77 @@
+// chartdata.rs
+// 谱面本身：读谱时读进来、导出时写出去的数据
+// 由这些数据算出来的东西（速度积分、trail位置等）都在timeline.rs里，这里不存
+use serde::{Serialize, Deserialize};
+
+use super::bpm::BPM;
+use super::timesignature::TimeSignature;
+use super::speed::Speed;
+use super::note::Note;
+use super::beat::BeatPosition;
+use super::format::SourceFormat;
+use super::timing::BpmTiming;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ChartData {
+    pub version: i32,
+    pub offset: f64,
+    pub metadata: Vec<String>, // 不认识的"# ..."表头行，原样保存
+    #[serde(skip)]
+    pub source_format: SourceFormat, // 读入txt时的原始排版，用于无损导出
+    pub bpm: Vec<BPM>,
+    pub time_signature: Vec<TimeSignature>,
+    pub speed: Vec<Speed>,
+    pub note: Vec<Note>,
+}
+
+impl ChartData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 这个函数的作用是对bpm、拍号、speed、note按时间排序
+    pub fn sort(&mut self) {
+        self.bpm.sort_by(|a: &BPM, b: &BPM| a.time.total_cmp(&b.time));
+        self.speed.sort_by(|a: &Speed, b: &Speed| a.time.total_cmp(&b.time));
+        self.note.sort_by(|a: &Note, b: &Note| a.get_time().total_cmp(&b.get_time()));
+        self.time_signature.sort_by(|a: &TimeSignature, b: &TimeSignature| a.time.total_cmp(&b.time));
+    }
+
+    // 这个函数的作用是按当前bpm给所有note算出拍数位置，已有的会被覆盖
+    pub fn compute_note_beats(&mut self, max_denominator: i32) {
+        self.sort();
+        let bpm_timing: BpmTiming = BpmTiming::new(&self.bpm);
+        for note in self.note.iter_mut() {
+            let position: BeatPosition = BeatPosition::from_beat(bpm_timing.time_to_beat(note.get_time()), max_denominator);
+            note.set_beat(Some(position));
+        }
+    }
+
+    // 这个函数的作用是按note的拍数位置重新计算time，没有拍数位置的note不动
+    pub fn retime_notes(&mut self) {
+        self.sort();
+        let bpm_timing: BpmTiming = BpmTiming::new(&self.bpm);
+        for note in self.note.iter_mut() {
+            if let Some(position) = note.get_beat() {
+                note.set_time(bpm_timing.beat_to_time(position.to_beat()));
+            }
+        }
+        self.sort();
+    }
+
+    // 这个函数的作用是替换bpm并让note保持在原来的拍上
+    // 还没有拍数位置的note先按旧的bpm算出来，再按新的bpm重新计算time
+    pub fn replace_bpm(&mut self, bpm: Vec<BPM>, max_denominator: i32) {
+        self.sort();
+        let bpm_timing: BpmTiming = BpmTiming::new(&self.bpm);
+        for note in self.note.iter_mut() {
+            if note.get_beat().is_none() {
+                let position: BeatPosition = BeatPosition::from_beat(bpm_timing.time_to_beat(note.get_time()), max_denominator);
+                note.set_beat(Some(position));
+            }
+        }
+        self.bpm = bpm;
+        self.retime_notes();
+    }
+}
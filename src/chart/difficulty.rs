@@ -0,0 +1,273 @@
+// difficulty.rs
+// 难度估计：把谱面按section_length切成小段，每段算四项负荷（strain），再合成一个难度数值
+//     density: 每秒的note数，不同note按权重算，slide中间的tick也算
+//     rotation: trail平均每秒转多少度
+//     reading: 流速每秒变化多少，加上倒流的时间占比
+//     slide: 每秒的slide复杂度，slide转的角度越大、tick越多越复杂
+// 每段的total是四项加权求和后再和前一段的total按decay衰减叠加，连续的高负荷会越叠越高
+// 最后的rating是所有段的total从大到小按top_weight的几何级数加权平均，只看最难的部分
+//
+// 段数最多max_sections段，采样最多max_samples次，谱面太长时加大section_length和sample_interval
+//
+// 权重没有对照官谱校准过，只保证同一套参数下不同谱面之间可以比较
+use super::chart::Chart;
+use super::note::Note;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DifficultyOptions {
+    pub section_length: f64, // 毫秒
+    pub sample_interval: f64, // 算rotation和reading时的采样间隔，毫秒
+    pub decay: f64, // 上一段的total留下多少，0到1
+    pub top_weight: f64, // rating里第i难的一段的权重是top_weight的i次方
+    pub max_sections: usize,
+    pub max_samples: usize,
+    // note的权重
+    pub tap_weight: f64,
+    pub flick_weight: f64,
+    pub slide_weight: f64,
+    pub slide_tick_weight: f64,
+    pub catch_weight: f64,
+    pub rotate_weight: f64,
+    pub bomb_weight: f64,
+    // 四项合成total时的权重
+    pub density_weight: f64,
+    pub rotation_weight: f64,
+    pub reading_weight: f64,
+    pub slide_complexity_weight: f64,
+    pub reversal_weight: f64, // reading里倒流时间占比的权重
+}
+
+impl Default for DifficultyOptions {
+    fn default() -> Self {
+        Self {
+            section_length: 400.0,
+            sample_interval: 20.0,
+            decay: 0.6,
+            top_weight: 0.95,
+            max_sections: 10_000, // 400ms一段时大约66分钟
+            max_samples: 200_000, // 20ms一次时大约66分钟
+            tap_weight: 1.0,
+            flick_weight: 1.2,
+            slide_weight: 1.0,
+            slide_tick_weight: 0.3,
+            catch_weight: 0.5,
+            rotate_weight: 1.5,
+            bomb_weight: 0.3,
+            density_weight: 1.0,
+            rotation_weight: 1.0 / 60.0,
+            reading_weight: 1.0,
+            slide_complexity_weight: 1.0,
+            reversal_weight: 5.0,
+        }
+    }
+}
+
+// 一段的负荷，time是这一段的开始时间
+// density、rotation、reading、slide是这一段自己的值，total叠加了前面的段
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Strain {
+    pub time: f64,
+    pub density: f64,
+    pub rotation: f64,
+    pub reading: f64,
+    pub slide: f64,
+    pub total: f64,
+}
+
+// rating和四项的值都是按最难的几段加权平均的结果
+#[derive(Clone, Debug, Default)]
+pub struct Difficulty {
+    pub rating: f64,
+    pub density: f64,
+    pub rotation: f64,
+    pub reading: f64,
+    pub slide: f64,
+    pub strains: Vec<Strain>,
+}
+
+// 这个函数的作用是估计谱面的难度，没有note时全是0
+pub fn estimate(chart: &Chart, options: &DifficultyOptions) -> Difficulty {
+    let notes: &[Note] = &chart.data().note;
+    if notes.is_empty() {
+        return Difficulty::default();
+    }
+    let start: f64 = notes[0].get_time();
+    let end: f64 = chart.timeline().note_end_time().iter().copied().fold(start, f64::max);
+    let (count, section_length): (usize, f64) = match bounded_steps(end - start, options.section_length, options.max_sections) {
+        Some(steps) => steps,
+        None => return Difficulty::default(),
+    };
+    let (samples, sample_interval): (usize, f64) = match bounded_steps(end - start, options.sample_interval, options.max_samples) {
+        Some(steps) => steps,
+        None => return Difficulty::default(),
+    };
+    let count: usize = count.max(1);
+    let seconds: f64 = section_length / 1000.0;
+    let section = |time: f64| (((time - start) / section_length).max(0.0) as usize).min(count - 1);
+
+    let mut strains: Vec<Strain> = (0..count)
+        .map(|i| Strain { time: start + i as f64 * section_length, ..Strain::default() })
+        .collect();
+
+    // density和slide
+    for note in notes.iter() {
+        let weight: f64 = match note {
+            Note::Tap(_) => options.tap_weight,
+            Note::Flick(_) => options.flick_weight,
+            Note::Slide(_) => options.slide_weight,
+            Note::Catch(_) => options.catch_weight,
+            Note::Rotate(_) => options.rotate_weight,
+            Note::Bomb(_) => options.bomb_weight,
+            Note::Trail(_) => 0.0,
+        };
+        strains[section(note.get_time())].density += weight / seconds;
+
+        if let Note::Slide(slide) = note {
+            let ticks: Vec<f64> = slide.tick_times(chart);
+            for &time in ticks.iter() {
+                strains[section(time)].density += options.slide_tick_weight / seconds;
+            }
+            let turn: f64 = (slide.end_degree - slide.degree).abs() as f64;
+            let complexity: f64 = 1.0 + turn / 90.0 + ticks.len() as f64 * 0.25;
+            strains[section(slide.time)].slide += complexity / seconds;
+        }
+    }
+
+    // rotation和reading：每个采样间隔算在它开始时所在的段里
+    let time_at = |i: usize| start + i as f64 * sample_interval;
+    let degrees: Vec<f64> = (0..=samples).map(|i| chart.find_degree_by_time(time_at(i)) as f64).collect();
+    let distances: Vec<f64> = (0..=samples).map(|i| chart.find_distance_by_time(time_at(i))).collect();
+    let speeds: Vec<f64> = distances.windows(2).map(|pair| (pair[1] - pair[0]) / sample_interval).collect();
+    let samples_per_section: f64 = section_length / sample_interval;
+    for i in 0..samples {
+        let strain: &mut Strain = &mut strains[section(time_at(i))];
+        // trail的角度在[0, 180)里，按最近的方向算
+        let turn: f64 = (degrees[i + 1] - degrees[i] + 90.0).rem_euclid(180.0) - 90.0;
+        strain.rotation += turn.abs() / seconds;
+        if i > 0 {
+            strain.reading += (speeds[i] - speeds[i - 1]).abs() / seconds;
+        }
+        if speeds[i] < 0.0 {
+            strain.reading += options.reversal_weight / samples_per_section;
+        }
+    }
+
+    let mut previous: f64 = 0.0;
+    for strain in strains.iter_mut() {
+        let raw: f64 = strain.density * options.density_weight
+            + strain.rotation * options.rotation_weight
+            + strain.reading * options.reading_weight
+            + strain.slide * options.slide_complexity_weight;
+        strain.total = previous * options.decay + raw;
+        previous = strain.total;
+    }
+
+    Difficulty {
+        rating: top_weighted(strains.iter().map(|strain| strain.total), options.top_weight),
+        density: top_weighted(strains.iter().map(|strain| strain.density), options.top_weight),
+        rotation: top_weighted(strains.iter().map(|strain| strain.rotation), options.top_weight),
+        reading: top_weighted(strains.iter().map(|strain| strain.reading), options.top_weight),
+        slide: top_weighted(strains.iter().map(|strain| strain.slide), options.top_weight),
+        strains,
+    }
+}
+
+// 从大到小排序后按weight的几何级数加权平均
+fn top_weighted(values: impl Iterator<Item = f64>, weight: f64) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(|a, b| b.total_cmp(a));
+    let (mut sum, mut total_weight, mut w): (f64, f64, f64) = (0.0, 0.0, 1.0);
+    for value in values {
+        sum += value * w;
+        total_weight += w;
+        w *= weight;
+    }
+    if total_weight > 0.0 { sum / total_weight } else { 0.0 }
+}
+
+// 把长为length的时间按step切开，切出来超过max段时加大step
+// 返回段数和实际的step，step不合法、length不是有限值或者max为0时返回None
+fn bounded_steps(length: f64, step: f64, max: usize) -> Option<(usize, f64)> {
+    if step.is_nan() || step <= 0.0 || !length.is_finite() || max == 0 {
+        return None;
+    }
+    let length: f64 = length.max(0.0);
+    let step: f64 = step.max(length / max as f64);
+    Some((((length / step).ceil() as usize).min(max), step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::note::{Tap, Trail};
+
+    fn chart(notes: Vec<Note>) -> Chart {
+        let mut data: ChartData = ChartData::new();
+        data.note = notes;
+        Chart::new(data)
+    }
+
+    fn taps(interval: f64, length: f64) -> Chart {
+        let count: usize = (length / interval) as usize;
+        chart((0..=count).map(|i| Note::Tap(Tap::new(i as f64 * interval, 0.0))).collect())
+    }
+
+    // 每500ms在0度和degree度之间来回
+    fn zigzag(degree: f32) -> Chart {
+        chart((0..=8).map(|i| Note::Trail(Trail::new(i as f64 * 500.0, if i % 2 == 0 { 0.0 } else { degree }, 0.0, 0.0, 0.0))).collect())
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn strains_follow_sections_and_decay() {
+        // 0ms和1000ms各一个tap，400ms一段切成3段，最后一个tap算在最后一段
+        let difficulty: Difficulty = estimate(&taps(1000.0, 1000.0), &DifficultyOptions::default());
+        let times: Vec<f64> = difficulty.strains.iter().map(|strain| strain.time).collect();
+        assert_eq!(times, vec![0.0, 400.0, 800.0]);
+        let densities: Vec<f64> = difficulty.strains.iter().map(|strain| strain.density).collect();
+        assert_eq!(densities, vec![2.5, 0.0, 2.5]);
+        for strain in difficulty.strains.iter() {
+            assert_close(strain.rotation, 0.0);
+            assert_close(strain.reading, 0.0);
+        }
+        // total = 上一段的total * decay + 这一段的负荷
+        let totals: Vec<f64> = difficulty.strains.iter().map(|strain| strain.total).collect();
+        assert_close(totals[0], 2.5);
+        assert_close(totals[1], 1.5);
+        assert_close(totals[2], 3.4);
+    }
+
+    #[test]
+    fn rating_increases_with_density() {
+        let options: DifficultyOptions = DifficultyOptions::default();
+        let ratings: Vec<f64> = [400.0, 200.0, 100.0, 50.0].iter().map(|&interval| estimate(&taps(interval, 4000.0), &options).rating).collect();
+        assert!(ratings.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ratings);
+    }
+
+    #[test]
+    fn rating_increases_with_rotation() {
+        let options: DifficultyOptions = DifficultyOptions::default();
+        let ratings: Vec<f64> = [0.0, 20.0, 40.0, 80.0].iter().map(|&degree| estimate(&zigzag(degree), &options).rating).collect();
+        assert!(ratings.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ratings);
+    }
+
+    #[test]
+    fn sections_and_samples_are_capped() {
+        assert_eq!(bounded_steps(1000.0, 400.0, 10), Some((3, 400.0)));
+        assert_eq!(bounded_steps(1e12, 1e-9, 1000), Some((1000, 1e9)));
+        assert_eq!(bounded_steps(f64::INFINITY, 400.0, 10), None);
+        assert_eq!(bounded_steps(1000.0, f64::NAN, 10), None);
+        assert_eq!(bounded_steps(1000.0, 0.0, 10), None);
+
+        // 很长的谱面加上很小的段长也只切max_sections段
+        let options: DifficultyOptions = DifficultyOptions { section_length: 1e-3, sample_interval: 1e-3, max_sections: 100, max_samples: 1000, ..DifficultyOptions::default() };
+        let difficulty: Difficulty = estimate(&taps(1e9, 1e9), &options);
+        assert_eq!(difficulty.strains.len(), 100);
+        assert_close(difficulty.strains[1].time, 1e7);
+        assert!(difficulty.rating > 0.0);
+    }
+}
@@ -0,0 +1,223 @@
+// easing.rs
+// 变速事件的缓动：描述速度从上一个事件变到这个事件的过程
+//
+// txt里的smooth字段:
+//     0: Step，官谱，保持上一个速度直到这个事件
+//     1: Linear，官谱，速度线性变化
+//     2-10: Quad/Cubic/Sine的In/Out/InOut，本工具的扩展
+//     11: Bezier，本工具的扩展，后面跟x1,y1,x2,y2四个字段
+// 扩展的编号只在表头有EXTENSION_HEADER时生效，没有时和官谱一样，1以外的编号都当作Step
+// 导出时有扩展的缓动就自动写上EXTENSION_HEADER
+use std::f64::consts::PI;
+
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+pub const BEZIER_CODE: i32 = 11;
+pub const EXTENSION_HEADER: &str = "# Extension Easing";
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Step,
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    // 和css的cubic-bezier一样，两端固定在(0,0)和(1,1)
+    Bezier { x1: f32, y1: f32, x2: f32, y2: f32 },
+}
+
+impl Easing {
+    // txt里的smooth编号，Bezier的四个参数另外写
+    pub fn code(&self) -> i32 {
+        match self {
+            Easing::Step => 0,
+            Easing::Linear => 1,
+            Easing::QuadIn => 2,
+            Easing::QuadOut => 3,
+            Easing::QuadInOut => 4,
+            Easing::CubicIn => 5,
+            Easing::CubicOut => 6,
+            Easing::CubicInOut => 7,
+            Easing::SineIn => 8,
+            Easing::SineOut => 9,
+            Easing::SineInOut => 10,
+            Easing::Bezier { .. } => BEZIER_CODE,
+        }
+    }
+
+    // Bezier需要参数，不能只靠编号得到
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Easing::Step),
+            1 => Some(Easing::Linear),
+            2 => Some(Easing::QuadIn),
+            3 => Some(Easing::QuadOut),
+            4 => Some(Easing::QuadInOut),
+            5 => Some(Easing::CubicIn),
+            6 => Some(Easing::CubicOut),
+            7 => Some(Easing::CubicInOut),
+            8 => Some(Easing::SineIn),
+            9 => Some(Easing::SineOut),
+            10 => Some(Easing::SineInOut),
+            _ => None,
+        }
+    }
+
+    // 官谱里只有0和1
+    pub fn is_official(&self) -> bool {
+        matches!(self, Easing::Step | Easing::Linear)
+    }
+
+    // 进度x(0到1)时速度变化了多少(0到1)
+    pub fn ease(&self, x: f64) -> f64 {
+        let x: f64 = x.clamp(0.0, 1.0);
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => x,
+            Easing::QuadIn => x * x,
+            Easing::QuadOut => 1.0 - (1.0 - x).powi(2),
+            Easing::QuadInOut => if x < 0.5 { 2.0 * x * x } else { 1.0 - 2.0 * (1.0 - x).powi(2) },
+            Easing::CubicIn => x.powi(3),
+            Easing::CubicOut => 1.0 - (1.0 - x).powi(3),
+            Easing::CubicInOut => if x < 0.5 { 4.0 * x.powi(3) } else { 1.0 - 4.0 * (1.0 - x).powi(3) },
+            Easing::SineIn => 1.0 - (PI * x / 2.0).cos(),
+            Easing::SineOut => (PI * x / 2.0).sin(),
+            Easing::SineInOut => (1.0 - (PI * x).cos()) / 2.0,
+            Easing::Bezier { x1, y1, x2, y2 } => bezier_ease(*x1 as f64, *y1 as f64, *x2 as f64, *y2 as f64, x),
+        }
+    }
+
+    // ease从0到x的积分，Bezier没有解析式，用辛普森公式数值积分
+    pub fn integral(&self, x: f64) -> f64 {
+        let x: f64 = x.clamp(0.0, 1.0);
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => x * x / 2.0,
+            Easing::QuadIn => x.powi(3) / 3.0,
+            Easing::QuadOut => x * x - x.powi(3) / 3.0,
+            Easing::QuadInOut => {
+                if x < 0.5 {
+                    2.0 * x.powi(3) / 3.0
+                } else {
+                    1.0 / 12.0 + (x - 0.5) + 2.0 / 3.0 * ((1.0 - x).powi(3) - 0.125)
+                }
+            },
+            Easing::CubicIn => x.powi(4) / 4.0,
+            Easing::CubicOut => x + ((1.0 - x).powi(4) - 1.0) / 4.0,
+            Easing::CubicInOut => if x < 0.5 { x.powi(4) } else { x - 0.5 + (1.0 - x).powi(4) },
+            Easing::SineIn => x - 2.0 / PI * (PI * x / 2.0).sin(),
+            Easing::SineOut => 2.0 / PI * (1.0 - (PI * x / 2.0).cos()),
+            Easing::SineInOut => x / 2.0 - (PI * x).sin() / (2.0 * PI),
+            Easing::Bezier { .. } => {
+                let steps: usize = 64; // 必须是偶数
+                let h: f64 = x / steps as f64;
+                let mut sum: f64 = self.ease(0.0) + self.ease(x);
+                for i in 1..steps {
+                    let weight: f64 = if i % 2 == 1 { 4.0 } else { 2.0 };
+                    sum += weight * self.ease(h * i as f64);
+                }
+                sum * h / 3.0
+            },
+        }
+    }
+
+    // 从速度speed_start缓动到speed_end，总时长duration，经过elapsed时走过的距离
+    pub fn segment_distance(&self, speed_start: f64, speed_end: f64, duration: f64, elapsed: f64) -> f64 {
+        if duration <= 0.0 {
+            return speed_start * elapsed;
+        }
+        let x: f64 = elapsed / duration;
+        if x > 1.0 {
+            // 超出这一段之后保持speed_end
+            return duration * (speed_start + (speed_end - speed_start) * self.integral(1.0)) + speed_end * (elapsed - duration);
+        }
+        duration * (speed_start * x + (speed_end - speed_start) * self.integral(x))
+    }
+}
+
+// 已知贝塞尔曲线的x求y，x1和x2在0到1之间时x关于参数t单调，二分求t
+fn bezier_ease(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let (x1, x2): (f64, f64) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+    let bezier = |p1: f64, p2: f64, t: f64| {
+        3.0 * (1.0 - t).powi(2) * t * p1 + 3.0 * (1.0 - t) * t * t * p2 + t.powi(3)
+    };
+    let (mut low, mut high): (f64, f64) = (0.0, 1.0);
+    for _ in 0..50 {
+        let mid: f64 = (low + high) / 2.0;
+        if bezier(x1, x2, mid) < x {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    bezier(y1, y2, (low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::format::WriteOptions;
+    use crate::chart::parse::{ParseOptions, ParseReport};
+    use crate::chart::speed::Speed;
+
+    fn load(text: &str) -> (Chart, ParseReport) {
+        Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::strict()).unwrap()
+    }
+
+    fn write(chart: &Chart, options: &WriteOptions) -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        chart.write_official_with_options(&mut buffer, options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn easings(chart: &Chart) -> Vec<Easing> {
+        chart.data().speed.iter().map(|speed| speed.easing).collect()
+    }
+
+    fn chart_text(header: &str, speeds: &str) -> String {
+        format!("# Version 2\n{}\n# BPM\n0,120\n\n# Speed\n{}\n# Note\n0,100,10\n", header, speeds)
+    }
+
+    #[test]
+    fn official_codes_other_than_linear_are_step() {
+        let text: String = chart_text("", "0,1,0\n100,1,1\n200,2,2\n300,1,10\n400,1,11\n500,1,-3\n");
+        let (chart, report) = load(&text);
+        assert_eq!(easings(&chart), vec![Easing::Step, Easing::Linear, Easing::Step, Easing::Step, Easing::Step, Easing::Step]);
+        assert_eq!(report.ignored.iter().filter(|diagnostic| diagnostic.to_string().contains("read as step")).count(), 4);
+        assert!(report.is_clean());
+        assert_eq!(write(&chart, &WriteOptions::default()), text);
+        assert!(!write(&chart, &WriteOptions::canonical(None)).contains(EXTENSION_HEADER));
+    }
+
+    #[test]
+    fn extension_header_enables_extended_codes() {
+        let text: String = chart_text(&format!("{}\n", EXTENSION_HEADER), "0,1,0\n100,1,1\n200,2,2\n300,1,10\n400,1,11,0.25,0.1,0.25,1\n");
+        let (chart, report) = load(&text);
+        assert_eq!(easings(&chart), vec![Easing::Step, Easing::Linear, Easing::QuadIn, Easing::SineInOut, Easing::Bezier { x1: 0.25, y1: 0.1, x2: 0.25, y2: 1.0 }]);
+        assert!(report.is_clean());
+        assert!(chart.data().metadata.is_empty());
+        assert_eq!(write(&chart, &WriteOptions::default()), text);
+
+        let unknown: String = chart_text(&format!("{}\n", EXTENSION_HEADER), "0,1,12\n");
+        assert!(Chart::from_official_reader(unknown.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn extended_easings_write_extension_header() {
+        let mut data: ChartData = ChartData::new();
+        data.speed = vec![Speed::new(0.0, 1.0, Easing::Step), Speed::new(100.0, 2.0, Easing::CubicOut)];
+        let chart: Chart = Chart::new(data);
+        let text: String = write(&chart, &WriteOptions::default());
+        assert!(text.starts_with(&format!("# Version 0\n{}\n", EXTENSION_HEADER)));
+        assert_eq!(easings(&load(&text).0), easings(&chart));
+    }
+}
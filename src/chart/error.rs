@@ -0,0 +1,160 @@
+// error.rs
+use std::fmt;
+use std::io;
+
+// 读谱时的错误，line和column都从1开始计数
+// json格式无法得到行列号，用key（例如note[3].time）指出出错位置，line和column为0
+// ParseNumber和UnknownNoteType在txt和json里都会出现，txt里key为空
+#[derive(Debug)]
+pub enum ChartError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    BadSectionHeader {
+        path: String,
+        line: usize,
+        column: usize,
+        header: String,
+    },
+    FieldCount {
+        path: String,
+        line: usize,
+        column: usize,
+        kind: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    ParseNumber {
+        path: String,
+        line: usize,
+        column: usize,
+        key: String,
+        value: String,
+    },
+    OutsideSection {
+        path: String,
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    UnknownNoteType {
+        path: String,
+        line: usize,
+        column: usize,
+        key: String,
+        note_type: String,
+    },
+    UnknownEasing {
+        path: String,
+        line: usize,
+        column: usize,
+        code: String,
+    },
+    UnknownSlideType {
+        path: String,
+        line: usize,
+        column: usize,
+        code: String,
+    },
+    MissingJsonKey {
+        path: String,
+        key: String,
+    },
+    Json {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl ChartError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        ChartError::Io { path: path.to_string(), source }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            ChartError::Io { path, .. } => path,
+            ChartError::BadSectionHeader { path, .. } => path,
+            ChartError::FieldCount { path, .. } => path,
+            ChartError::ParseNumber { path, .. } => path,
+            ChartError::OutsideSection { path, .. } => path,
+            ChartError::UnknownNoteType { path, .. } => path,
+            ChartError::UnknownEasing { path, .. } => path,
+            ChartError::UnknownSlideType { path, .. } => path,
+            ChartError::MissingJsonKey { path, .. } => path,
+            ChartError::Json { path, .. } => path,
+        }
+    }
+
+    // 返回(行, 列)，没有位置信息时返回None
+    pub fn location(&self) -> Option<(usize, usize)> {
+        let (line, column) = match self {
+            ChartError::BadSectionHeader { line, column, .. } => (*line, *column),
+            ChartError::FieldCount { line, column, .. } => (*line, *column),
+            ChartError::ParseNumber { line, column, .. } => (*line, *column),
+            ChartError::OutsideSection { line, column, .. } => (*line, *column),
+            ChartError::UnknownNoteType { line, column, .. } => (*line, *column),
+            ChartError::UnknownEasing { line, column, .. } => (*line, *column),
+            ChartError::UnknownSlideType { line, column, .. } => (*line, *column),
+            ChartError::Json { line, column, .. } => (*line, *column),
+            ChartError::Io { .. } | ChartError::MissingJsonKey { .. } => return None,
+        };
+        if line == 0 {
+            None
+        } else {
+            Some((line, column))
+        }
+    }
+
+    // json里出错的key，没有时返回None
+    pub fn key(&self) -> Option<&str> {
+        let key: &str = match self {
+            ChartError::ParseNumber { key, .. } => key,
+            ChartError::UnknownNoteType { key, .. } => key,
+            ChartError::MissingJsonKey { key, .. } => key,
+            _ => return None,
+        };
+        if key.is_empty() {
+            None
+        } else {
+            Some(key)
+        }
+    }
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path())?;
+        if let Some((line, column)) = self.location() {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        match self {
+            ChartError::ParseNumber { key, .. } | ChartError::UnknownNoteType { key, .. } if !key.is_empty() => write!(f, ": {}", key)?,
+            _ => {},
+        }
+        match self {
+            ChartError::Io { source, .. } => write!(f, ": {}", source),
+            ChartError::BadSectionHeader { header, .. } => write!(f, ": bad section header `{}`", header),
+            ChartError::FieldCount { kind, expected, found, .. } => write!(f, ": {} line expects {} fields, found {}", kind, expected, found),
+            ChartError::ParseNumber { value, .. } => write!(f, ": cannot parse `{}` as a number", value),
+            ChartError::OutsideSection { text, .. } => write!(f, ": line outside of any section: `{}`", text),
+            ChartError::UnknownNoteType { note_type, .. } => write!(f, ": unknown note type `{}`", note_type),
+            ChartError::UnknownEasing { code, .. } => write!(f, ": unknown speed easing `{}`", code),
+            ChartError::UnknownSlideType { code, .. } => write!(f, ": unknown slide type `{}`", code),
+            ChartError::MissingJsonKey { key, .. } => write!(f, ": missing json key `{}`", key),
+            ChartError::Json { message, .. } => write!(f, ": {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ChartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChartError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
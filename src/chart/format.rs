@@ -0,0 +1,319 @@
+// format.rs
+// 官谱txt的输出格式
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+
+use super::bpm::BPM;
+use super::note::Note;
+use super::speed::Speed;
+use super::easing::Easing;
+use super::timesignature::TimeSignature;
+
+// 有的编辑器会在文件开头加上UTF-8 BOM，见SourceFormat::has_bom
+pub(crate) const BOM: char = '\u{feff}';
+
+// 导出txt的选项
+// precision: None时浮点数用能精确读回的最短写法，Some(n)时固定保留n位小数
+// preserve_source: 为true时尽量按读入时的原文输出，保证读了再写字节不变
+#[derive(Clone, Copy)]
+pub struct WriteOptions {
+    pub precision: Option<usize>,
+    pub preserve_source: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self { precision: None, preserve_source: true }
+    }
+}
+
+impl WriteOptions {
+    pub fn canonical(precision: Option<usize>) -> Self {
+        Self { precision, preserve_source: false }
+    }
+}
+
+pub(crate) fn format_number<T: Display>(value: T, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision, value),
+        None => format!("{}", value),
+    }
+}
+
+// 读入时每一行的原文和它后面的换行符，文件最后一行没有换行时ending为空
+#[derive(Clone)]
+enum SourceLine {
+    Text { text: String, ending: String },                  // 节标题、空行、宽松模式下跳过的行等，原样输出
+    Record { canonical: String, raw: String, ending: String }, // 谱面数据，同时记下规范写法和原文
+}
+
+// 读入txt时记下的原始排版
+// 每一行的原文和换行符都按行记录，只要谱面没被改动，就能按原样写回
+#[derive(Clone)]
+pub struct SourceFormat {
+    lines: Vec<SourceLine>,
+    pub(crate) line_ending: String, // 不按原样输出时用的换行符，取原文里用得最多的一种
+    pub(crate) has_offset: bool,
+    pub(crate) has_easing_extension: bool,
+    pub(crate) has_bom: bool, // 文件开头有UTF-8 BOM，导出时也写上
+}
+
+impl Default for SourceFormat {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            line_ending: "\n".to_string(),
+            has_offset: false,
+            has_easing_extension: false,
+            has_bom: false,
+        }
+    }
+}
+
+impl SourceFormat {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub(crate) fn push_text(&mut self, line: &str, ending: &str) {
+        self.lines.push(SourceLine::Text { text: line.to_string(), ending: ending.to_string() });
+    }
+
+    pub(crate) fn push_record(&mut self, canonical: String, raw: &str, ending: &str) {
+        self.lines.push(SourceLine::Record { canonical, raw: raw.to_string(), ending: ending.to_string() });
+    }
+
+    // 读完之后调用，选出不按原样输出时用的换行符
+    pub(crate) fn finish(&mut self) {
+        let (mut crlf, mut lf): (usize, usize) = (0, 0);
+        for line in &self.lines {
+            match line {
+                SourceLine::Text { ending, .. } | SourceLine::Record { ending, .. } => match ending.as_str() {
+                    "\r\n" => crlf += 1,
+                    "\n" => lf += 1,
+                    _ => {},
+                },
+            }
+        }
+        self.line_ending = if crlf > lf { "\r\n" } else { "\n" }.to_string();
+    }
+
+    // 规范写法 -> 原文，同一个规范写法出现多次时按读入顺序依次取
+    pub(crate) fn raw_lines(&self) -> RawLines<'_> {
+        let mut raws: HashMap<&str, VecDeque<&str>> = HashMap::new();
+        for line in &self.lines {
+            if let SourceLine::Record { canonical, raw, .. } = line {
+                raws.entry(canonical.as_str()).or_default().push_back(raw.as_str());
+            }
+        }
+        RawLines { raws }
+    }
+
+    // 谱面数据和读入时一致时，返回原始排版：(原文, 换行符)
+    // 表头（#开头的行）只要求内容一致，顺序按原文；其余数据要求顺序也一致
+    pub(crate) fn layout_for<'a>(&'a self, header: &[String], body: &[String]) -> Option<Vec<(&'a str, &'a str)>> {
+        let mut output: Vec<(&'a str, &'a str)> = Vec::with_capacity(self.lines.len());
+        let mut source_header: Vec<&str> = Vec::new();
+        let mut next: usize = 0;
+        for line in &self.lines {
+            match line {
+                SourceLine::Text { text, ending } => output.push((text, ending)),
+                SourceLine::Record { canonical, raw, ending } => {
+                    if canonical.starts_with('#') {
+                        source_header.push(canonical);
+                    } else {
+                        if body.get(next) != Some(canonical) {
+                            return None;
+                        }
+                        next += 1;
+                    }
+                    output.push((raw, ending));
+                },
+            }
+        }
+        let mut header: Vec<&str> = header.iter().map(String::as_str).collect();
+        header.sort_unstable();
+        source_header.sort_unstable();
+        if next != body.len() || header != source_header {
+            return None;
+        }
+        Some(output)
+    }
+}
+
+// 谱面改动过、不能按原样输出时，没改过的行仍然用原文
+pub(crate) struct RawLines<'a> {
+    raws: HashMap<&'a str, VecDeque<&'a str>>,
+}
+
+impl<'a> RawLines<'a> {
+    pub(crate) fn take<'b>(&mut self, canonical: &'b str) -> &'b str where 'a: 'b {
+        self.raws.get_mut(canonical).and_then(VecDeque::pop_front).unwrap_or(canonical)
+    }
+}
+
+// 以下是每一行谱面数据的写法，precision为None时就是规范写法
+pub(crate) fn bpm_line(bpm: &BPM, precision: Option<usize>) -> String {
+    format!("{},{}", format_number(bpm.time, precision), format_number(bpm.bpm, precision))
+}
+
+pub(crate) fn speed_line(speed: &Speed, precision: Option<usize>) -> String {
+    let line: String = format!("{},{},{}", format_number(speed.time, precision), format_number(speed.speed, precision), speed.easing.code());
+    match speed.easing {
+        Easing::Bezier { x1, y1, x2, y2 } => {
+            let f = |value: f32| format_number(value, precision);
+            format!("{},{},{},{},{}", line, f(x1), f(y1), f(x2), f(y2))
+        },
+        _ => line,
+    }
+}
+
+pub(crate) fn time_signature_line(signature: &TimeSignature, precision: Option<usize>) -> String {
+    format!("{},{},{}", format_number(signature.time, precision), signature.numerator, signature.denominator)
+}
+
+pub(crate) fn note_line(note: &Note, precision: Option<usize>) -> String {
+    let t = |value: f64| format_number(value, precision);
+    let f = |value: f32| format_number(value, precision);
+    match note {
+        Note::Tap(tap) => format!("0,{},{}", t(tap.time), f(tap.degree)),
+        Note::Flick(flick) => format!("1,{},{}", t(flick.time), f(flick.degree)),
+        Note::Slide(slide) => format!("2,{},{},{},{},{},{},{},{}", t(slide.time), f(slide.degree), slide.slidetype.code(), f(slide.end_degree), slide.snap, slide.amount, f(slide.prev_curv), f(slide.next_curv)),
+        Note::Rotate(rotate) => format!("4,{},{},{},{},{}", t(rotate.time), f(rotate.degree), f(rotate.delta), f(rotate.prev_curv), f(rotate.next_curv)),
+        Note::Catch(catch) => format!("5,{},{}", t(catch.time), f(catch.degree)),
+        Note::Bomb(bomb) => format!("6,{},{}", t(bomb.time), f(bomb.degree)),
+        Note::Trail(trail) => format!("11,{},{},{},{},{}", t(trail.time), f(trail.degree), f(trail.delta), f(trail.prev_curv), f(trail.next_curv)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::parse::ParseOptions;
+
+    fn load(text: &str) -> Chart {
+        Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::strict()).unwrap().0
+    }
+
+    fn write(chart: &Chart, options: &WriteOptions) -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        chart.write_official_with_options(&mut buffer, options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    const BODY: &str = "\n# BPM\n0,120\n\n# Speed\n0,1,0\n\n# Note\n0,100,10\n";
+
+    #[test]
+    fn offset_header_round_trips() {
+        for header in ["# Version 2\n# Offset 0\n", "# Version 2\n# Offset -12.5\n", "# Version 2\n"] {
+            let text: String = format!("{}{}", header, BODY);
+            let chart: Chart = load(&text);
+            assert_eq!(write(&chart, &WriteOptions::default()), text);
+            // 不保留原文时Offset行也要按读入时的有无输出
+            assert_eq!(write(&chart, &WriteOptions::canonical(None)), text);
+        }
+        assert_eq!(load(&format!("# Version 2\n# Offset -12.5\n{}", BODY)).data().offset, -12.5);
+    }
+
+    #[test]
+    fn unknown_headers_are_metadata() {
+        let text: String = format!("# Version 2\n# Offset 3\n# OffsetFoo 5\n# Charter someone\n{}", BODY);
+        let chart: Chart = load(&text);
+        assert_eq!(chart.data().offset, 3.0);
+        assert_eq!(chart.data().metadata, vec!["# OffsetFoo 5".to_string(), "# Charter someone".to_string()]);
+        assert_eq!(write(&chart, &WriteOptions::default()), text);
+    }
+
+    #[test]
+    fn edited_offset_is_written() {
+        let mut chart: Chart = load(&format!("# Version 2\n{}", BODY));
+        chart.edit(|data| data.offset = 40.0);
+        assert!(write(&chart, &WriteOptions::default()).starts_with("# Version 2\n# Offset 40\n"));
+    }
+
+    // 同一个规范写法的三种原文，最后一行没有换行
+    const DUPLICATES: &str = "# Version 2\n# BPM\n0,120.0\n\n# Speed\n0,1,0\n\n# Note\n0,100,5\n0,100.0,5\n0,1e2,5\n1,200.50,90";
+
+    #[test]
+    fn duplicate_values_round_trip() {
+        let chart: Chart = load(DUPLICATES);
+        assert_eq!(write(&chart, &WriteOptions::default()), DUPLICATES);
+        // 改动后不能按原样输出，没改过的行仍然按读入顺序用各自的原文
+        let mut edited: Chart = chart.clone();
+        edited.edit(|data| data.offset = 10.0);
+        let text: String = write(&edited, &WriteOptions::default());
+        let notes: Vec<&str> = text.lines().skip_while(|line| *line != "# Note").skip(1).collect();
+        assert_eq!(notes, vec!["0,100,5", "0,100.0,5", "0,1e2,5", "1,200.50,90"]);
+    }
+
+    #[test]
+    fn line_endings_round_trip() {
+        let crlf: String = DUPLICATES.replace('\n', "\r\n") + "\r\n";
+        assert_eq!(write(&load(&crlf), &WriteOptions::default()), crlf);
+        // 换行符混用时每一行按原样写回
+        let mixed: &str = "# Version 2\n# BPM\r\n0,120\n\r\n# Speed\n0,1,0\r\n\n# Note\r\n0,100,10\n";
+        let chart: Chart = load(mixed);
+        assert_eq!(write(&chart, &WriteOptions::default()), mixed);
+        // 不按原样输出时用原文里用得最多的换行符
+        let canonical: String = write(&load(&crlf), &WriteOptions::canonical(None));
+        assert!(canonical.ends_with("\r\n"));
+        assert!(!canonical.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn header_order_round_trips() {
+        let text: String = format!("# Charter someone\n# Version 2\n# Offset 3\n{}", BODY);
+        assert_eq!(write(&load(&text), &WriteOptions::default()), text);
+    }
+
+    #[test]
+    fn skipped_lines_are_kept_in_layout() {
+        let text: &str = "# Version 2\n# BPM\n0,120\n\n# Speed\n0,1,0\n\n# Note\n0,100,10\n9,oops\n1,200,90\n";
+        let (chart, report) = Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::lenient()).unwrap();
+        assert_eq!(report.skipped_lines.len(), 1);
+        assert_eq!(chart.data().note.len(), 2);
+        assert_eq!(write(&chart, &WriteOptions::default()), text);
+    }
+
+    // 参考谱面，一个LF一个CRLF（最后一行没有换行），.gitattributes里关掉了换行符转换
+    const FIXTURES: [(&str, &[u8]); 2] = [
+        ("reference_lf.txt", include_bytes!("../../tests/fixtures/reference_lf.txt")),
+        ("reference_crlf.txt", include_bytes!("../../tests/fixtures/reference_crlf.txt")),
+    ];
+
+    fn write_bytes(chart: &Chart, options: &WriteOptions) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::new();
+        chart.write_official_with_options(&mut buffer, options).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn reference_charts_round_trip() {
+        for (name, bytes) in FIXTURES {
+            let (chart, report) = Chart::from_official_reader_with_options(bytes, &ParseOptions::strict()).unwrap();
+            assert!(report.is_clean(), "{}", name);
+            assert!(!chart.data().note.is_empty(), "{}", name);
+            assert!(write_bytes(&chart, &WriteOptions::default()) == bytes, "{} does not round trip", name);
+            // 规范写法读回来谱面不变，换行符沿用原文
+            let canonical: Vec<u8> = write_bytes(&chart, &WriteOptions::canonical(None));
+            let crlf: bool = name.contains("crlf");
+            assert_eq!(canonical.windows(2).any(|pair| pair == b"\r\n"), crlf, "{}", name);
+            assert!(write_bytes(&load(std::str::from_utf8(&canonical).unwrap()), &WriteOptions::canonical(None)) == canonical, "{}", name);
+        }
+    }
+
+    #[test]
+    fn bom_round_trips() {
+        for (name, bytes) in FIXTURES {
+            let with_bom: Vec<u8> = [BOM.to_string().as_bytes(), bytes].concat();
+            let (chart, report) = Chart::from_official_reader_with_options(with_bom.as_slice(), &ParseOptions::strict()).unwrap();
+            assert!(report.is_clean(), "{}", name);
+            assert_eq!(chart.data().version, 2, "{}", name);
+            assert!(write_bytes(&chart, &WriteOptions::default()) == with_bom, "{} with a BOM does not round trip", name);
+            let canonical: Vec<u8> = write_bytes(&chart, &WriteOptions::canonical(None));
+            let without: Chart = Chart::from_official_reader(bytes).unwrap();
+            assert!(canonical[BOM.len_utf8()..] == write_bytes(&without, &WriteOptions::canonical(None)), "{}", name);
+        }
+    }
+}
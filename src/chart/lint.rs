@@ -0,0 +1,594 @@
+// lint.rs
+// 谱面检查：找出能读进来但是不合理的地方，例如bpm为0、slide的snap为0、note重叠等
+//
+// 每条规则有固定的编号（LintCode::code），以后加新规则只往后加编号，不改已有的编号
+// 严重程度：
+//     Error: 谱面的计算会出错（除以0、时间倒流等）
+//     Warning: 能正常计算，但很可能是写错了
+//     Info: 仅供参考
+//
+// 谱面读进来以后会按时间排序，原始顺序里时间没有排好这一条只能在读谱时检查，
+// 结果放在ParseReport::lints里，其他规则用lint对整个谱面检查
+use std::fmt;
+
+use super::chart::Chart;
+use super::chartdata::ChartData;
+use super::note::Note;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintCode {
+    NoteBeforeFirstBpm,
+    NonPositiveBpm,
+    SlideZeroSnap,
+    OverlappingNotes,
+    UnreachableCatch,
+    RotateFullTurn,
+    DegreeOutOfRange,
+    UnsortedSource,
+    NegativeDistance,
+    RotationSpeed,
+    RotationAcceleration,
+}
+
+impl LintCode {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintCode::NoteBeforeFirstBpm => "L001",
+            LintCode::NonPositiveBpm => "L002",
+            LintCode::SlideZeroSnap => "L003",
+            LintCode::OverlappingNotes => "L004",
+            LintCode::UnreachableCatch => "L005",
+            LintCode::RotateFullTurn => "L006",
+            LintCode::DegreeOutOfRange => "L007",
+            LintCode::UnsortedSource => "L008",
+            LintCode::NegativeDistance => "L009",
+            LintCode::RotationSpeed => "L010",
+            LintCode::RotationAcceleration => "L011",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintCode::NonPositiveBpm | LintCode::SlideZeroSnap => Severity::Error,
+            LintCode::NoteBeforeFirstBpm
+            | LintCode::OverlappingNotes
+            | LintCode::UnreachableCatch
+            | LintCode::RotateFullTurn
+            | LintCode::DegreeOutOfRange
+            | LintCode::UnsortedSource
+            | LintCode::NegativeDistance
+            | LintCode::RotationSpeed
+            | LintCode::RotationAcceleration => Severity::Warning,
+        }
+    }
+}
+
+// 一条检查结果
+// time是出问题的谱面时间，和时间无关的问题为None
+// notes是相关note在谱面note列表里的下标，UnsortedSource里是原始顺序的下标
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub code: LintCode,
+    pub severity: Severity,
+    pub time: Option<f64>,
+    pub notes: Vec<usize>,
+    pub message: String,
+}
+
+impl Lint {
+    pub fn new(code: LintCode, time: Option<f64>, notes: Vec<usize>, message: String) -> Self {
+        Self { code, severity: code.severity(), time, notes, message }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.code.code(), self.severity)?;
+        if let Some(time) = self.time {
+            write!(f, " at {}ms", time)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// 转速和角加速度按手机角度（find_phone_degree_by_time）每隔rotation_sample_interval毫秒采样一次来算
+// 加速度和采样间隔有关，改小采样间隔时折线拐角处的加速度会变大，上限也要跟着调
+// 采样次数最多max_rotation_samples次，谱面太长时加大采样间隔
+#[derive(Clone, Copy, Debug)]
+pub struct LintOptions {
+    pub time_tolerance: f64, // 时间相差不超过这么多毫秒算同一时间
+    pub degree_tolerance: f32, // 角度相差不超过这么多度算同一位置
+    pub max_rotation_speed: f64, // 度每秒
+    pub max_rotation_acceleration: f64, // 度每平方秒
+    pub rotation_sample_interval: f64, // 毫秒
+    pub max_rotation_samples: usize,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            time_tolerance: 1.0,
+            degree_tolerance: 1.0,
+            max_rotation_speed: 1440.0,
+            max_rotation_acceleration: 36000.0,
+            rotation_sample_interval: 20.0,
+            max_rotation_samples: 100_000, // 20ms一次时大约33分钟
+        }
+    }
+}
+
+// 这个函数的作用是检查整个谱面，结果按时间排序，和时间无关的在最前面
+pub fn lint(chart: &Chart, options: &LintOptions) -> Vec<Lint> {
+    let mut lints: Vec<Lint> = Vec::new();
+    lint_bpm(chart.data(), &mut lints);
+    lint_notes(chart.data(), &mut lints);
+    lint_overlapping(chart.data(), options, &mut lints);
+    lint_catches(chart.data(), options, &mut lints);
+    lint_distance(chart, &mut lints);
+    lint_rotation(chart, options, &mut lints);
+    lints.sort_by(|a, b| a.time.unwrap_or(f64::MIN).total_cmp(&b.time.unwrap_or(f64::MIN)));
+    lints
+}
+
+// 这个函数的作用是检查读谱时的原始顺序，data不能是排过序的
+pub fn lint_source_order(data: &ChartData) -> Vec<Lint> {
+    let mut lints: Vec<Lint> = Vec::new();
+    let mut check = |kind: &str, times: Vec<f64>, is_note: bool| {
+        for i in 1..times.len() {
+            if times[i] < times[i - 1] {
+                let notes: Vec<usize> = if is_note { vec![i - 1, i] } else { Vec::new() };
+                let message: String = format!("{} #{} at {}ms comes after {}ms", kind, i, times[i], times[i - 1]);
+                lints.push(Lint::new(LintCode::UnsortedSource, Some(times[i]), notes, message));
+            }
+        }
+    };
+    check("bpm", data.bpm.iter().map(|bpm| bpm.time).collect(), false);
+    check("time signature", data.time_signature.iter().map(|signature| signature.time).collect(), false);
+    check("speed", data.speed.iter().map(|speed| speed.time).collect(), false);
+    check("note", data.note.iter().map(|note| note.get_time()).collect(), true);
+    lints
+}
+
+fn lint_bpm(data: &ChartData, lints: &mut Vec<Lint>) {
+    for bpm in data.bpm.iter() {
+        if bpm.bpm <= 0.0 {
+            lints.push(Lint::new(LintCode::NonPositiveBpm, Some(bpm.time), Vec::new(), format!("bpm is {}", bpm.bpm)));
+        }
+    }
+
+    let first_bpm: f64 = match data.bpm.first() {
+        Some(bpm) => bpm.time,
+        None => {
+            lints.push(Lint::new(LintCode::NoteBeforeFirstBpm, None, Vec::new(), "chart has no bpm".to_string()));
+            return;
+        },
+    };
+    let before: Vec<usize> = (0..data.note.len()).filter(|&i| data.note[i].get_time() < first_bpm).collect();
+    if let Some(&first) = before.first() {
+        let message: String = format!("{} notes before the first bpm at {}ms", before.len(), first_bpm);
+        lints.push(Lint::new(LintCode::NoteBeforeFirstBpm, Some(data.note[first].get_time()), before, message));
+    }
+}
+
+// 只看单个note的规则
+fn lint_notes(data: &ChartData, lints: &mut Vec<Lint>) {
+    for (i, note) in data.note.iter().enumerate() {
+        let time: f64 = note.get_time();
+        // slide的end_degree可以超过360，表示跨过0度，不检查
+        let degree: Option<f32> = match note {
+            Note::Tap(tap) => Some(tap.degree),
+            Note::Flick(flick) => Some(flick.degree),
+            Note::Slide(slide) => Some(slide.degree),
+            Note::Rotate(rotate) => Some(rotate.degree),
+            Note::Trail(trail) => Some(trail.degree),
+            Note::Bomb(bomb) => Some(bomb.degree),
+            Note::Catch(_) => None,
+        };
+        if let Some(degree) = degree {
+            if !(0.0..360.0).contains(&degree) {
+                let message: String = format!("{} degree {} is outside [0, 360)", note.typename(), degree);
+                lints.push(Lint::new(LintCode::DegreeOutOfRange, Some(time), vec![i], message));
+            }
+        }
+
+        match note {
+            Note::Slide(slide) if slide.snap == 0 => {
+                lints.push(Lint::new(LintCode::SlideZeroSnap, Some(time), vec![i], "slide snap is 0".to_string()));
+            },
+            Note::Rotate(rotate) if rotate.delta.abs() >= 360.0 => {
+                let message: String = format!("rotate delta {} is a full turn or more", rotate.delta);
+                lints.push(Lint::new(LintCode::RotateFullTurn, Some(time), vec![i], message));
+            },
+            _ => {},
+        }
+    }
+}
+
+// 同一时间同一位置的两个note，catch只和catch比（catch的degree是在trail的哪一边）
+// trail和rotate不是要打的note，不检查
+fn lint_overlapping(data: &ChartData, options: &LintOptions, lints: &mut Vec<Lint>) {
+    let position = |note: &Note| match note {
+        Note::Tap(tap) => Some((false, tap.degree)),
+        Note::Flick(flick) => Some((false, flick.degree)),
+        Note::Slide(slide) => Some((false, slide.degree)),
+        Note::Bomb(bomb) => Some((false, bomb.degree)),
+        Note::Catch(catch) => Some((true, catch.degree * 180.0)),
+        Note::Rotate(_) | Note::Trail(_) => None,
+    };
+    let notes: &[Note] = &data.note;
+    for i in 0..notes.len() {
+        let (is_catch, degree) = match position(&notes[i]) {
+            Some(position) => position,
+            None => continue,
+        };
+        for j in i + 1..notes.len() {
+            if notes[j].get_time() - notes[i].get_time() > options.time_tolerance {
+                break;
+            }
+            let (other_is_catch, other_degree) = match position(&notes[j]) {
+                Some(position) => position,
+                None => continue,
+            };
+            let difference: f32 = (degree - other_degree).rem_euclid(360.0);
+            if is_catch == other_is_catch && difference.min(360.0 - difference) <= options.degree_tolerance {
+                let message: String = format!("{} and {} overlap at degree {}", notes[i].typename(), notes[j].typename(), degree);
+                lints.push(Lint::new(LintCode::OverlappingNotes, Some(notes[i].get_time()), vec![i, j], message));
+            }
+        }
+    }
+}
+
+// catch要落在trail上：谱面里要有trail，catch不能在第一个trail之前，degree只能是0或1（trail的两边）
+fn lint_catches(data: &ChartData, options: &LintOptions, lints: &mut Vec<Lint>) {
+    let first_trail: Option<f64> = data.note.iter()
+        .filter(|note| matches!(note, Note::Trail(_) | Note::Rotate(_)))
+        .map(|note| note.get_time())
+        .reduce(f64::min);
+    for (i, note) in data.note.iter().enumerate() {
+        let catch = match note {
+            Note::Catch(catch) => catch,
+            _ => continue,
+        };
+        let message: Option<String> = match first_trail {
+            None => Some("catch in a chart without any trail".to_string()),
+            Some(first) if catch.time < first - options.time_tolerance => Some(format!("catch before the first trail at {}ms", first)),
+            _ if catch.degree != 0.0 && catch.degree != 1.0 => Some(format!("catch degree {} is not on either side of the trail", catch.degree)),
+            _ => None,
+        };
+        if let Some(message) = message {
+            lints.push(Lint::new(LintCode::UnreachableCatch, Some(catch.time), vec![i], message));
+        }
+    }
+}
+
+// 积分变成负数的位置，每一段连续的负数只报一次
+// 在每个变速点、每个note的时间和每段缓动中间速度变号（积分最小）的时间上检查，最后一个变速之后的部分到最后一个note为止
+fn lint_distance(chart: &Chart, lints: &mut Vec<Lint>) {
+    let mut times: Vec<f64> = chart.timeline().speed_distance().iter().map(|speed_distance| speed_distance.time)
+        .chain(chart.timeline().speed_sign_change_times())
+        .chain(chart.data().note.iter().map(|note| note.get_time()))
+        .collect();
+    times.sort_by(f64::total_cmp);
+    let mut negative: bool = false;
+    for time in times {
+        let distance: f64 = chart.find_distance_by_time(time);
+        if distance < 0.0 && !negative {
+            let message: String = format!("speed events make the total distance negative ({:.1})", distance);
+            lints.push(Lint::new(LintCode::NegativeDistance, Some(time), Vec::new(), message));
+        }
+        negative = distance < 0.0;
+    }
+}
+
+// 转得太快或者加速太猛、手机跟不上的地方，连续超过上限的一段只报一次
+// notes是这一段前后最近的两个note之间（包括这两个）的所有note
+fn lint_rotation(chart: &Chart, options: &LintOptions, lints: &mut Vec<Lint>) {
+    let notes: &[Note] = &chart.data().note;
+    if notes.is_empty() {
+        return;
+    }
+    let start: f64 = notes[0].get_time();
+    let end: f64 = chart.timeline().note_end_time().iter().copied().fold(start, f64::max);
+    let (count, interval): (usize, f64) = match rotation_samples(start, end, options) {
+        Some(samples) => samples,
+        None => return,
+    };
+
+    // 手机角度在[0, 180)里，相邻两次采样的差按最近的方向算
+    let degrees: Vec<f64> = (0..=count).map(|i| chart.find_phone_degree_by_time(start + i as f64 * interval) as f64).collect();
+    let speeds: Vec<f64> = degrees.windows(2)
+        .map(|pair| ((pair[1] - pair[0] + 90.0).rem_euclid(180.0) - 90.0) / interval * 1000.0)
+        .collect();
+    let accelerations: Vec<f64> = speeds.windows(2)
+        .map(|pair| (pair[1] - pair[0]) / interval * 1000.0)
+        .collect();
+
+    // 第i个值对应的时间段是[start + i * interval, start + (i + width) * interval]
+    let mut report = |code: LintCode, values: &[f64], limit: f64, width: usize, unit: &str| {
+        let mut i: usize = 0;
+        while i < values.len() {
+            if values[i].abs() <= limit {
+                i += 1;
+                continue;
+            }
+            let first: usize = i;
+            let mut peak: f64 = 0.0;
+            while i < values.len() && values[i].abs() > limit {
+                peak = peak.max(values[i].abs());
+                i += 1;
+            }
+            let from: f64 = start + first as f64 * interval;
+            let to: f64 = start + (i - 1 + width) as f64 * interval;
+            let lo: usize = notes.partition_point(|note| note.get_time() <= from).saturating_sub(1);
+            let hi: usize = notes.partition_point(|note| note.get_time() < to).min(notes.len() - 1);
+            let message: String = format!("rotation reaches {:.0} {} (limit {:.0}) between {:.0}ms and {:.0}ms", peak, unit, limit, from, to);
+            lints.push(Lint::new(code, Some(from), (lo..=hi).collect(), message));
+        }
+    };
+    report(LintCode::RotationSpeed, &speeds, options.max_rotation_speed, 1, "deg/s");
+    report(LintCode::RotationAcceleration, &accelerations, options.max_rotation_acceleration, 2, "deg/s^2");
+}
+
+// 从start到end的采样次数和采样间隔，间隔不合法或者时间不是有限值时返回None
+fn rotation_samples(start: f64, end: f64, options: &LintOptions) -> Option<(usize, f64)> {
+    let interval: f64 = options.rotation_sample_interval;
+    if interval.is_nan() || interval <= 0.0 || !(end - start).is_finite() || options.max_rotation_samples == 0 {
+        return None;
+    }
+    let length: f64 = (end - start).max(0.0);
+    let interval: f64 = interval.max(length / options.max_rotation_samples as f64);
+    let count: usize = ((length / interval).ceil() as usize).min(options.max_rotation_samples);
+    Some((count, interval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::bpm::BPM;
+    use crate::chart::easing::Easing;
+    use crate::chart::note::{Tap, Flick, Slide, SlideType, Rotate, Catch, Trail};
+    use crate::chart::parse::ParseOptions;
+    use crate::chart::speed::Speed;
+
+    // 120bpm，一个trail和一个tap，本身没有任何问题
+    fn base() -> ChartData {
+        let mut data: ChartData = ChartData::new();
+        data.bpm = vec![BPM::new(0.0, 120.0)];
+        data.note = vec![Note::Trail(Trail::new(0.0, 0.0, 0.0, 0.0, 0.0)), Note::Tap(Tap::new(1000.0, 90.0))];
+        data
+    }
+
+    fn lints_of(data: ChartData, code: LintCode) -> Vec<Lint> {
+        let mut lints: Vec<Lint> = lint(&Chart::new(data), &LintOptions::default());
+        lints.retain(|lint| lint.code == code);
+        lints
+    }
+
+    fn with_note(note: Note) -> ChartData {
+        let mut data: ChartData = base();
+        data.note.push(note);
+        data
+    }
+
+    // 手机跟着tap转，1000ms到2000ms匀速从0度转到60度（60度每秒），之后停住
+    fn chart() -> Chart {
+        let mut data: ChartData = ChartData::new();
+        data.note = vec![
+            Note::Tap(Tap::new(1000.0, 0.0)),
+            Note::Tap(Tap::new(2000.0, 60.0)),
+            Note::Tap(Tap::new(3000.0, 60.0)),
+        ];
+        Chart::new(data)
+    }
+
+    fn rotation_lints(chart: &Chart, options: &LintOptions, code: LintCode) -> Vec<Lint> {
+        let mut lints: Vec<Lint> = Vec::new();
+        lint_rotation(chart, options, &mut lints);
+        lints.retain(|lint| lint.code == code);
+        lints
+    }
+
+    #[test]
+    fn rotation_speed_threshold() {
+        let chart: Chart = chart();
+        let options: LintOptions = LintOptions { max_rotation_speed: 70.0, ..LintOptions::default() };
+        assert!(rotation_lints(&chart, &options, LintCode::RotationSpeed).is_empty());
+        let options: LintOptions = LintOptions { max_rotation_speed: 50.0, ..LintOptions::default() };
+        let lints: Vec<Lint> = rotation_lints(&chart, &options, LintCode::RotationSpeed);
+        assert_eq!(lints.len(), 1);
+        assert!((lints[0].time.unwrap() - 1000.0).abs() < 1e-6);
+        assert_eq!(lints[0].notes, vec![0, 1]);
+    }
+
+    #[test]
+    fn rotation_acceleration_threshold() {
+        // 2000ms时转速从60度每秒突然变成0，按20ms采样加速度是3000度每平方秒
+        let chart: Chart = chart();
+        let options: LintOptions = LintOptions { max_rotation_acceleration: 4000.0, ..LintOptions::default() };
+        assert!(rotation_lints(&chart, &options, LintCode::RotationAcceleration).is_empty());
+        let options: LintOptions = LintOptions { max_rotation_acceleration: 2000.0, ..LintOptions::default() };
+        let lints: Vec<Lint> = rotation_lints(&chart, &options, LintCode::RotationAcceleration);
+        assert_eq!(lints.len(), 1);
+        assert!((lints[0].time.unwrap() - 1980.0).abs() < 1e-6);
+        // 1980ms到2020ms前后最近的note是1000ms和3000ms的两个
+        assert_eq!(lints[0].notes, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rotation_samples_are_capped() {
+        let options: LintOptions = LintOptions { rotation_sample_interval: 1e-9, max_rotation_samples: 1000, ..LintOptions::default() };
+        let (count, interval): (usize, f64) = rotation_samples(0.0, 1e12, &options).unwrap();
+        assert!(count <= 1000);
+        assert!(count as f64 * interval >= 1e12);
+        assert_eq!(rotation_samples(0.0, f64::INFINITY, &options), None);
+        let options: LintOptions = LintOptions { rotation_sample_interval: f64::NAN, ..LintOptions::default() };
+        assert_eq!(rotation_samples(0.0, 1000.0, &options), None);
+        // 没超过上限时按原来的间隔
+        assert_eq!(rotation_samples(0.0, 1000.0, &LintOptions::default()), Some((50, 20.0)));
+
+        // 很长的谱面加上很小的采样间隔也只采样max_rotation_samples次
+        let mut chart: Chart = chart();
+        chart.edit(|data| data.note.push(Note::Tap(Tap::new(1e9, 60.0))));
+        let options: LintOptions = LintOptions { rotation_sample_interval: 1e-3, max_rotation_samples: 10_000, ..LintOptions::default() };
+        assert_eq!(rotation_samples(1000.0, 1e9, &options).unwrap().0, 10_000);
+        let mut lints: Vec<Lint> = Vec::new();
+        lint_rotation(&chart, &options, &mut lints);
+    }
+
+    #[test]
+    fn base_chart_is_clean() {
+        assert!(lint(&Chart::new(base()), &LintOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn note_before_first_bpm() {
+        let mut data: ChartData = base();
+        data.bpm = vec![BPM::new(500.0, 120.0)];
+        let lints: Vec<Lint> = lints_of(data, LintCode::NoteBeforeFirstBpm);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![0]);
+        assert_eq!(lints[0].time, Some(0.0));
+
+        let mut data: ChartData = base();
+        data.bpm.clear();
+        let lints: Vec<Lint> = lints_of(data, LintCode::NoteBeforeFirstBpm);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].time, None);
+        assert!(lints_of(base(), LintCode::NoteBeforeFirstBpm).is_empty());
+    }
+
+    #[test]
+    fn non_positive_bpm() {
+        let mut data: ChartData = base();
+        data.bpm.push(BPM::new(500.0, 0.0));
+        data.bpm.push(BPM::new(800.0, -60.0));
+        let lints: Vec<Lint> = lints_of(data, LintCode::NonPositiveBpm);
+        assert_eq!(lints.iter().map(|lint| lint.time).collect::<Vec<_>>(), vec![Some(500.0), Some(800.0)]);
+        assert_eq!(lints[0].severity, Severity::Error);
+
+        let mut data: ChartData = base();
+        data.bpm.push(BPM::new(500.0, 240.0));
+        assert!(lints_of(data, LintCode::NonPositiveBpm).is_empty());
+    }
+
+    #[test]
+    fn slide_zero_snap() {
+        let lints: Vec<Lint> = lints_of(with_note(Note::Slide(Slide::new(2000.0, 90.0, SlideType::TapHead, 180.0, 0, 4, 0.0, 0.0))), LintCode::SlideZeroSnap);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![2]);
+        assert!(lints_of(with_note(Note::Slide(Slide::new(2000.0, 90.0, SlideType::TapHead, 180.0, 4, 4, 0.0, 0.0))), LintCode::SlideZeroSnap).is_empty());
+    }
+
+    #[test]
+    fn overlapping_notes() {
+        let lints: Vec<Lint> = lints_of(with_note(Note::Flick(Flick::new(1000.5, 90.5))), LintCode::OverlappingNotes);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![1, 2]);
+        // 角度差按圆周算，359度和0度只差1度
+        let mut data: ChartData = base();
+        data.note.push(Note::Tap(Tap::new(3000.0, 359.5)));
+        data.note.push(Note::Tap(Tap::new(3000.0, 0.0)));
+        assert_eq!(lints_of(data, LintCode::OverlappingNotes).len(), 1);
+
+        assert!(lints_of(with_note(Note::Flick(Flick::new(1000.0, 100.0))), LintCode::OverlappingNotes).is_empty());
+        assert!(lints_of(with_note(Note::Flick(Flick::new(1010.0, 90.0))), LintCode::OverlappingNotes).is_empty());
+        // trail不算要打的note
+        assert!(lints_of(with_note(Note::Trail(Trail::new(1000.0, 90.0, 0.0, 0.0, 0.0))), LintCode::OverlappingNotes).is_empty());
+    }
+
+    #[test]
+    fn unreachable_catch() {
+        // 不在trail的两边
+        let lints: Vec<Lint> = lints_of(with_note(Note::Catch(Catch::new(2000.0, 0.5))), LintCode::UnreachableCatch);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![2]);
+        // 没有trail
+        let mut data: ChartData = base();
+        data.note = vec![Note::Catch(Catch::new(2000.0, 0.0))];
+        assert_eq!(lints_of(data, LintCode::UnreachableCatch).len(), 1);
+        // 在第一个trail之前
+        let mut data: ChartData = base();
+        data.note = vec![Note::Catch(Catch::new(500.0, 0.0)), Note::Trail(Trail::new(1000.0, 0.0, 0.0, 0.0, 0.0))];
+        assert_eq!(lints_of(data, LintCode::UnreachableCatch).len(), 1);
+
+        assert!(lints_of(with_note(Note::Catch(Catch::new(2000.0, 1.0))), LintCode::UnreachableCatch).is_empty());
+    }
+
+    #[test]
+    fn rotate_full_turn() {
+        let lints: Vec<Lint> = lints_of(with_note(Note::Rotate(Rotate::new(2000.0, 90.0, -360.0, 0.0, 0.0))), LintCode::RotateFullTurn);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![2]);
+        assert!(lints_of(with_note(Note::Rotate(Rotate::new(2000.0, 90.0, 180.0, 0.0, 0.0))), LintCode::RotateFullTurn).is_empty());
+    }
+
+    #[test]
+    fn degree_out_of_range() {
+        let lints: Vec<Lint> = lints_of(with_note(Note::Tap(Tap::new(2000.0, 360.0))), LintCode::DegreeOutOfRange);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].notes, vec![2]);
+        assert_eq!(lints_of(with_note(Note::Flick(Flick::new(2000.0, -1.0))), LintCode::DegreeOutOfRange).len(), 1);
+        assert!(lints_of(with_note(Note::Tap(Tap::new(2000.0, 359.0))), LintCode::DegreeOutOfRange).is_empty());
+        // slide的end_degree可以超过360
+        assert!(lints_of(with_note(Note::Slide(Slide::new(2000.0, 300.0, SlideType::TapHead, 420.0, 4, 4, 0.0, 0.0))), LintCode::DegreeOutOfRange).is_empty());
+    }
+
+    #[test]
+    fn unsorted_source() {
+        let text: &str = "# Version 2\n# BPM\n0,120\n# Speed\n0,1,0\n# Note\n0,1000,90\n0,500,90\n0,2000,90\n";
+        let (chart, report) = Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::lenient()).unwrap();
+        assert_eq!(report.lints.len(), 1);
+        assert_eq!(report.lints[0].code, LintCode::UnsortedSource);
+        assert_eq!(report.lints[0].notes, vec![0, 1]);
+        assert_eq!(report.lints[0].time, Some(500.0));
+        // 读进来以后已经排好序，再检查就没有了
+        assert!(lint_source_order(chart.data()).is_empty());
+
+        let text: &str = "# Version 2\n# BPM\n0,120\n# Speed\n0,1,0\n# Note\n0,500,90\n0,1000,90\n";
+        let (_, report) = Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::strict()).unwrap();
+        assert!(report.lints.is_empty());
+    }
+
+    #[test]
+    fn negative_distance() {
+        // 1000ms之后以-2倍速往回走，2000ms前后积分变成负数
+        let mut data: ChartData = with_note(Note::Tap(Tap::new(3000.0, 90.0)));
+        data.speed = vec![Speed::new(0.0, 1.0, Easing::Step), Speed::new(1000.0, -2.0, Easing::Step)];
+        let lints: Vec<Lint> = lints_of(data, LintCode::NegativeDistance);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].time, Some(3000.0));
+
+        let mut data: ChartData = with_note(Note::Tap(Tap::new(3000.0, 90.0)));
+        data.speed = vec![Speed::new(0.0, 1.0, Easing::Step), Speed::new(1000.0, -0.2, Easing::Step)];
+        assert!(lints_of(data, LintCode::NegativeDistance).is_empty());
+    }
+
+    #[test]
+    fn negative_distance_inside_eased_segment() {
+        // 300ms时积分是300，之后速度从-3线性变到5，675ms时积分最小（-262.5），1300ms时又回到1300
+        // 变速点和note的时间上积分都不是负数
+        let mut data: ChartData = base();
+        data.note.push(Note::Tap(Tap::new(2000.0, 90.0)));
+        data.speed = vec![Speed::new(0.0, 1.0, Easing::Step), Speed::new(300.0, -3.0, Easing::Step), Speed::new(1300.0, 5.0, Easing::Linear)];
+        let lints: Vec<Lint> = lints_of(data, LintCode::NegativeDistance);
+        assert_eq!(lints.len(), 1);
+        assert!((lints[0].time.unwrap() - 675.0).abs() < 1e-6, "{:?}", lints[0].time);
+    }
+}
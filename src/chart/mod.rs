@@ -1,8 +1,36 @@
 // chart/mod.rs
+#[allow(clippy::module_inception)]
 pub mod chart;
-pub use chart::Chart;
+pub use chart::{Chart, HitSoundKind};
+pub mod chartdata;
+pub use chartdata::ChartData;
+pub mod timeline;
+pub use timeline::ChartTimeline;
+pub mod search;
+pub use search::SearchMode;
+pub mod trailcurve;
+pub use trailcurve::{TrailCurve, TrailSegment};
+pub mod bezier;
+pub use bezier::BezierCurve;
+pub mod error;
+pub use error::ChartError;
+pub mod parse;
+pub use parse::{ParseOptions, ParseReport};
+pub mod lint;
+pub use lint::{Lint, LintCode, LintOptions, Severity};
+pub mod difficulty;
+pub use difficulty::{Difficulty, DifficultyOptions};
+pub mod schema;
+pub mod format;
+pub use format::WriteOptions;
 pub mod speed;
+pub mod easing;
+pub use easing::Easing;
 pub mod bpm;
+pub mod timing;
+pub mod timesignature;
 pub mod note;
+pub mod beat;
+pub use beat::{BeatPosition, MeasurePosition};
 pub mod speeddistance;
 pub mod traildistance;
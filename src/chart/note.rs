@@ -1,36 +1,88 @@
 // note.rs
-use serde::{Serialize, Serializer, Deserialize};
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone)]
+use super::beat::BeatPosition;
+use super::chart::Chart;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Tap {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
 }
 
 impl Tap {
-    pub fn new(time: f32, degree: f32) -> Self {
-        Self { time, degree }
+    pub fn new(time: f64, degree: f32) -> Self {
+        Self { time, beat: None, degree }
     }
 
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Flick {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
 }
 
 impl Flick {
-    pub fn new(time: f32, degree: f32) -> Self {
-        Self { time, degree }
+    pub fn new(time: f64, degree: f32) -> Self {
+        Self { time, beat: None, degree }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+// slide开头的样式，txt和json里都是编号：0是tap头，1是flick头，2是小slide，3是大slide
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(into = "i32", try_from = "i32")]
+pub enum SlideType {
+    TapHead,
+    FlickHead,
+    Small,
+    Big,
+}
+
+impl SlideType {
+    pub fn code(&self) -> i32 {
+        match self {
+            SlideType::TapHead => 0,
+            SlideType::FlickHead => 1,
+            SlideType::Small => 2,
+            SlideType::Big => 3,
+        }
+    }
+}
+
+impl TryFrom<i32> for SlideType {
+    type Error = String;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(SlideType::TapHead),
+            1 => Ok(SlideType::FlickHead),
+            2 => Ok(SlideType::Small),
+            3 => Ok(SlideType::Big),
+            _ => Err(format!("unknown slide type `{}`", code)),
+        }
+    }
+}
+
+impl From<SlideType> for i32 {
+    fn from(slidetype: SlideType) -> Self {
+        slidetype.code()
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Slide {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
-    pub slidetype: i32,
+    #[schemars(with = "i32")]
+    pub slidetype: SlideType,
     pub end_degree: f32,
     pub snap: i32,
     pub amount: i32,
@@ -39,14 +91,29 @@ pub struct Slide {
 }
 
 impl Slide {
-    pub fn new(time: f32, degree: f32, slidetype: i32, end_degree: f32, snap: i32, amount: i32, prev_curv: f32, next_curv: f32) -> Self {
-        Self { time, degree, slidetype, end_degree, snap, amount, prev_curv, next_curv }
+    #[allow(clippy::too_many_arguments)] // 参数和txt里的字段一一对应
+    pub fn new(time: f64, degree: f32, slidetype: SlideType, end_degree: f32, snap: i32, amount: i32, prev_curv: f32, next_curv: f32) -> Self {
+        Self { time, beat: None, degree, slidetype, end_degree, snap, amount, prev_curv, next_curv }
+    }
+
+    // 这个函数的作用是算出slide中间每个tick的时间（不包括开头），snap填4就是4分音符
+    // 按拍数往后走，slide中间有bpm变化时tick的间隔跟着变，最后一个tick在开头之后(amount - 1) / snap拍
+    pub fn tick_times(&self, chart: &Chart) -> Vec<f64> {
+        if self.snap == 0 {
+            return Vec::new();
+        }
+        let start_beat: f64 = chart.chart_time_to_beat(self.time);
+        (1..self.amount)
+            .map(|j| chart.beat_to_chart_time(start_beat + j as f64 / self.snap as f64))
+            .collect()
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Rotate {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
     pub delta: f32,
     pub prev_curv: f32,
@@ -54,26 +121,33 @@ pub struct Rotate {
 }
 
 impl Rotate {
-    pub fn new(time: f32, degree: f32, delta: f32, prev_curv: f32, next_curv: f32) -> Self {
-        Self { time, degree, delta, prev_curv, next_curv }
+    pub fn new(time: f64, degree: f32, delta: f32, prev_curv: f32, next_curv: f32) -> Self {
+        Self { time, beat: None, degree, delta, prev_curv, next_curv }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Catch {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
+    // 判定时手机的实际角度，只在导出json时由write_json填上，读取时忽略
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub truedegree: Option<f32>,
 }
 
 impl Catch {
-    pub fn new(time: f32, degree: f32) -> Self {
-        Self { time, degree }
+    pub fn new(time: f64, degree: f32) -> Self {
+        Self { time, beat: None, degree, truedegree: None }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Trail {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
     pub delta: f32,
     pub prev_curv: f32,
@@ -81,24 +155,29 @@ pub struct Trail {
 }
 
 impl Trail {
-    pub fn new(time: f32, degree: f32, delta: f32, prev_curv: f32, next_curv: f32) -> Self {
-        Self { time, degree, delta, prev_curv, next_curv}
+    pub fn new(time: f64, degree: f32, delta: f32, prev_curv: f32, next_curv: f32) -> Self {
+        Self { time, beat: None, degree, delta, prev_curv, next_curv }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Bomb {
-    pub time: f32,
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat: Option<BeatPosition>, // 拍数位置，修改bpm后用它重新计算time
     pub degree: f32,
 }
 
 impl Bomb {
-    pub fn new(time: f32, degree: f32) -> Self {
-        Self { time, degree }
+    pub fn new(time: f64, degree: f32) -> Self {
+        Self { time, beat: None, degree }
     }
 }
 
-#[derive(Deserialize, Clone)]
+// json里的note用typename区分类型，读写都只有typename，和json schema一致
+// 官谱里的数字类型编号见note_type，旧版(schema_version 1)只有type的json由schema::migrate补上typename
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+#[serde(tag = "typename")]
 pub enum Note {
     Tap(Tap),
     Flick(Flick),
@@ -109,24 +188,46 @@ pub enum Note {
     Bomb(Bomb),
 }
 
-impl Serialize for Note {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
+impl Note {
+    // 官谱里的note类型编号
+    pub fn note_type(&self) -> i32 {
         match self {
-            Note::Trail(_) => serializer.serialize_none(),
-            Note::Tap(tap) => tap.serialize(serializer),
-            Note::Flick(flick) => flick.serialize(serializer),
-            Note::Slide(slide) => slide.serialize(serializer),
-            Note::Rotate(rotate) => rotate.serialize(serializer),
-            Note::Catch(catch) => catch.serialize(serializer),
-            Note::Bomb(bomb) => bomb.serialize(serializer),
+            Note::Tap(_) => 0,
+            Note::Flick(_) => 1,
+            Note::Slide(_) => 2,
+            Note::Rotate(_) => 4,
+            Note::Catch(_) => 5,
+            Note::Bomb(_) => 6,
+            Note::Trail(_) => 11,
         }
     }
-}
-impl Note {
-    pub fn get_time(&self) -> f32 {
+
+    pub fn typename(&self) -> &'static str {
+        match self {
+            Note::Tap(_) => "Tap",
+            Note::Flick(_) => "Flick",
+            Note::Slide(_) => "Slide",
+            Note::Rotate(_) => "Rotate",
+            Note::Catch(_) => "Catch",
+            Note::Bomb(_) => "Bomb",
+            Note::Trail(_) => "Trail",
+        }
+    }
+
+    pub fn typename_from_type(note_type: i32) -> Option<&'static str> {
+        match note_type {
+            0 => Some("Tap"),
+            1 => Some("Flick"),
+            2 => Some("Slide"),
+            4 => Some("Rotate"),
+            5 => Some("Catch"),
+            6 => Some("Bomb"),
+            11 => Some("Trail"),
+            _ => None,
+        }
+    }
+
+    pub fn get_time(&self) -> f64 {
         match self {
             Note::Tap(tap) => tap.time,
             Note::Flick(flick) => flick.time,
@@ -137,4 +238,39 @@ impl Note {
             Note::Bomb(bomb) => bomb.time,
         }
     }
-}
\ No newline at end of file
+    pub fn set_time(&mut self, time: f64) {
+        match self {
+            Note::Tap(tap) => tap.time = time,
+            Note::Flick(flick) => flick.time = time,
+            Note::Slide(slide) => slide.time = time,
+            Note::Rotate(rotate) => rotate.time = time,
+            Note::Catch(catch) => catch.time = time,
+            Note::Trail(trail) => trail.time = time,
+            Note::Bomb(bomb) => bomb.time = time,
+        }
+    }
+
+    pub fn get_beat(&self) -> Option<BeatPosition> {
+        match self {
+            Note::Tap(tap) => tap.beat,
+            Note::Flick(flick) => flick.beat,
+            Note::Slide(slide) => slide.beat,
+            Note::Rotate(rotate) => rotate.beat,
+            Note::Catch(catch) => catch.beat,
+            Note::Trail(trail) => trail.beat,
+            Note::Bomb(bomb) => bomb.beat,
+        }
+    }
+
+    pub fn set_beat(&mut self, beat: Option<BeatPosition>) {
+        match self {
+            Note::Tap(tap) => tap.beat = beat,
+            Note::Flick(flick) => flick.beat = beat,
+            Note::Slide(slide) => slide.beat = beat,
+            Note::Rotate(rotate) => rotate.beat = beat,
+            Note::Catch(catch) => catch.beat = beat,
+            Note::Trail(trail) => trail.beat = beat,
+            Note::Bomb(bomb) => bomb.beat = beat,
+        }
+    }
+}
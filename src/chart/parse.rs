@@ -0,0 +1,376 @@
+// parse.rs
+use std::cell::RefCell;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::error::ChartError;
+use super::lint::Lint;
+
+// 读谱选项
+// strict为true时遇到任何问题（包括警告）都直接返回错误，为false时跳过坏行并记录到ParseReport里
+// 默认是严格模式，不带options的读谱函数也用严格模式，想要宽松读取需要明确传lenient()
+#[derive(Clone, Copy)]
+pub struct ParseOptions {
+    pub strict: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::strict()
+    }
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self { strict: true }
+    }
+
+    pub fn lenient() -> Self {
+        Self { strict: false }
+    }
+}
+
+// 一条诊断信息，line和column从1开始，json里为0
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(path: &str, line: usize, column: usize, message: String) -> Self {
+        Self { path: path.to_string(), line, column, message }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.path, self.line, self.column, self.message)
+        }
+    }
+}
+
+// 宽松模式下读谱时收集到的所有问题
+// skipped_lines: 被整行跳过的内容
+// warnings: 不影响结果但值得注意的内容
+// recovered: 格式不对但被修正后读进来的值
+// ignored: 没有内容、直接忽略的行（空行），官谱里很常见，不算在is_clean里
+// lints: 原始顺序里的问题（时间没有排好序），读进来以后会被排序，只能在这里检查，不算在is_clean里
+#[derive(Default, Debug)]
+pub struct ParseReport {
+    pub skipped_lines: Vec<ChartError>,
+    pub warnings: Vec<Diagnostic>,
+    pub recovered: Vec<Diagnostic>,
+    pub ignored: Vec<Diagnostic>,
+    pub lints: Vec<Lint>,
+}
+
+impl ParseReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 没有任何跳过、警告和修正
+    pub fn is_clean(&self) -> bool {
+        self.skipped_lines.is_empty() && self.warnings.is_empty() && self.recovered.is_empty()
+    }
+
+    pub(crate) fn skip(&mut self, error: ChartError) {
+        log::error!("{}, Skipping", error);
+        self.skipped_lines.push(error);
+    }
+
+    pub(crate) fn warn(&mut self, diagnostic: Diagnostic) {
+        log::warn!("{}", diagnostic);
+        self.warnings.push(diagnostic);
+    }
+
+    pub(crate) fn recover(&mut self, diagnostic: Diagnostic) {
+        log::warn!("{}", diagnostic);
+        self.recovered.push(diagnostic);
+    }
+
+    pub(crate) fn ignore(&mut self, diagnostic: Diagnostic) {
+        log::debug!("{}", diagnostic);
+        self.ignored.push(diagnostic);
+    }
+}
+
+// 宽松模式下可以修正的字段
+// 浮点数的parse能读出"NaN"、"inf"，超出f32范围的数也会变成inf，这些都当作读不出来
+pub(crate) trait FieldValue: FromStr + fmt::Display {
+    fn recover(value: &str) -> Option<Self>;
+
+    fn is_finite(&self) -> bool {
+        true
+    }
+}
+
+impl FieldValue for f32 {
+    fn recover(value: &str) -> Option<Self> {
+        value.trim().parse().ok()
+    }
+
+    fn is_finite(&self) -> bool {
+        f32::is_finite(*self)
+    }
+}
+
+impl FieldValue for f64 {
+    fn recover(value: &str) -> Option<Self> {
+        value.trim().parse().ok()
+    }
+
+    fn is_finite(&self) -> bool {
+        f64::is_finite(*self)
+    }
+}
+
+impl FieldValue for i32 {
+    // 整数字段写成了"4.0"这种形式也能读
+    fn recover(value: &str) -> Option<Self> {
+        let value: &str = value.trim();
+        value.parse().ok().or_else(|| {
+            value.parse::<f64>().ok().filter(|v| v.fract() == 0.0).map(|v| v as i32)
+        })
+    }
+}
+
+// 把一行按逗号拆开，同时记下每个字段的列号，用于报错
+pub(crate) struct LineFields<'a> {
+    path: &'a str,
+    line_no: usize,
+    strict: bool,
+    fields: Vec<(usize, &'a str)>,
+    recovered: RefCell<Vec<Diagnostic>>,
+}
+
+impl<'a> LineFields<'a> {
+    pub(crate) fn split(path: &'a str, line_no: usize, line: &'a str, options: &ParseOptions) -> Self {
+        let mut fields: Vec<(usize, &'a str)> = Vec::new();
+        let mut column: usize = 1;
+        for part in line.split(',') {
+            fields.push((column, part));
+            column += part.chars().count() + 1;
+        }
+        Self { path, line_no, strict: options.strict, fields, recovered: RefCell::new(Vec::new()) }
+    }
+
+    // 形如"# Version 1"的表头，按空白拆分
+    pub(crate) fn split_header(path: &'a str, line_no: usize, line: &'a str, options: &ParseOptions) -> Self {
+        let fields: Vec<(usize, &'a str)> = line
+            .split_whitespace()
+            .map(|part| (line[..part.as_ptr() as usize - line.as_ptr() as usize].chars().count() + 1, part))
+            .collect();
+        Self { path, line_no, strict: options.strict, fields, recovered: RefCell::new(Vec::new()) }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub(crate) fn column(&self, index: usize) -> usize {
+        self.fields[index].0
+    }
+
+    pub(crate) fn expect_len(&self, kind: &'static str, expected: usize) -> Result<(), ChartError> {
+        if self.fields.len() != expected {
+            let column: usize = self.fields.get(expected.min(self.fields.len().saturating_sub(1))).map_or(1, |f| f.0);
+            return Err(ChartError::FieldCount { path: self.path.to_string(), line: self.line_no, column, kind, expected, found: self.fields.len() });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn parse<T: FieldValue>(&self, index: usize) -> Result<T, ChartError> {
+        let (column, value) = self.fields[index];
+        if let Ok(result) = value.parse::<T>() {
+            if result.is_finite() {
+                return Ok(result);
+            }
+            return Err(ChartError::ParseNumber { path: self.path.to_string(), line: self.line_no, column, key: String::new(), value: value.to_string() });
+        }
+        if !self.strict {
+            if let Some(result) = T::recover(value).filter(FieldValue::is_finite) {
+                let message: String = format!("read `{}` as `{}`", value, result);
+                self.recovered.borrow_mut().push(Diagnostic::new(self.path, self.line_no, column, message));
+                return Ok(result);
+            }
+        }
+        Err(ChartError::ParseNumber { path: self.path.to_string(), line: self.line_no, column, key: String::new(), value: value.to_string() })
+    }
+
+    // 这一行读完之后把修正记录交给report
+    pub(crate) fn finish(self, report: &mut ParseReport) {
+        for diagnostic in self.recovered.into_inner() {
+            report.recover(diagnostic);
+        }
+    }
+}
+
+// serde_json的错误转换成ChartError，缺字段时转成MissingJsonKey
+pub(crate) fn json_error(path: &str, key: &str, error: serde_json::Error) -> ChartError {
+    let message: String = error.to_string();
+    if let Some(field) = message.strip_prefix("missing field `").and_then(|rest| rest.split('`').next()) {
+        let key: String = if key.is_empty() { field.to_string() } else { format!("{}.{}", key, field) };
+        return ChartError::MissingJsonKey { path: path.to_string(), key };
+    }
+    ChartError::Json { path: path.to_string(), line: error.line(), column: error.column(), message }
+}
+
+// 把json里key对应的value读成T
+// json的数字都是有限的，但超出f32范围的数读成f32会变成inf，写回json时是null，这里找出来当作读不出来的数
+pub(crate) fn from_json_value<T: DeserializeOwned + Serialize>(path: &str, key: &str, value: &Value) -> Result<T, ChartError> {
+    let result: T = serde_json::from_value(value.clone()).map_err(|e| json_error(path, key, e))?;
+    let parsed: Value = serde_json::to_value(&result).map_err(|e| json_error(path, key, e))?;
+    match non_finite_key(value, &parsed, key) {
+        Some((key, number)) => Err(ChartError::ParseNumber { path: path.to_string(), line: 0, column: 0, key, value: number }),
+        None => Ok(result),
+    }
+}
+
+// 原来是数字、读进来再写回去变成null的字段
+fn non_finite_key(original: &Value, parsed: &Value, key: &str) -> Option<(String, String)> {
+    let (original, parsed) = match (original.as_object(), parsed.as_object()) {
+        (Some(original), Some(parsed)) => (original, parsed),
+        _ => return None,
+    };
+    for (field, value) in original.iter() {
+        let child: String = format!("{}.{}", key, field);
+        match (value, parsed.get(field)) {
+            (Value::Number(number), Some(Value::Null)) => return Some((child, number.to_string())),
+            (Value::Object(_), Some(inner)) => {
+                if let Some(found) = non_finite_key(value, inner, &child) {
+                    return Some(found);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::bpm::BPM;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::note::{Note, Tap};
+
+    const MALFORMED: &str = "# Version 2\n# Offset 0\nstray line\n# BPM\n0,120\n\n# Speed\n0,1,0\n# Note\n0,100,10\n0,abc,10\n9,200,10\n0, 300 ,10\n";
+
+    fn lenient(text: &str) -> (Chart, ParseReport) {
+        Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::lenient()).unwrap()
+    }
+
+    #[test]
+    fn default_options_are_strict() {
+        assert!(ParseOptions::default().strict);
+    }
+
+    #[test]
+    fn strict_fails_on_first_problem() {
+        let error = Chart::from_official_reader_with_options(MALFORMED.as_bytes(), &ParseOptions::strict()).err().unwrap();
+        assert!(matches!(error, ChartError::OutsideSection { line: 3, column: 1, .. }), "{}", error);
+
+        let text: String = MALFORMED.replace("stray line\n", "");
+        let error = Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::strict()).err().unwrap();
+        assert!(matches!(error, ChartError::ParseNumber { line: 10, column: 3, .. }), "{}", error);
+    }
+
+    #[test]
+    fn plain_entry_points_are_strict() {
+        assert!(Chart::from_official_reader(MALFORMED.as_bytes()).is_err());
+        assert!(MALFORMED.parse::<Chart>().is_err());
+        assert!(Chart::from_json_reader("{\"schema_version\": 3, \"version\": 2, \"offset\": 0, \"bpm\": [], \"note\": []}".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn lenient_skips_and_reports() {
+        let (chart, report) = lenient(MALFORMED);
+        assert_eq!(chart.data().note.len(), 2);
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].line, 3);
+        assert_eq!(report.skipped_lines.len(), 2);
+        assert!(matches!(report.skipped_lines[0], ChartError::ParseNumber { line: 11, .. }));
+        assert!(matches!(report.skipped_lines[1], ChartError::UnknownNoteType { line: 12, .. }));
+        assert_eq!(report.recovered.len(), 1);
+        assert_eq!(report.recovered[0].line, 13);
+        assert_eq!(report.ignored.len(), 1);
+        assert_eq!(report.ignored[0].line, 6);
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn clean_file_has_clean_report() {
+        let text: &str = "# Version 2\n\n# BPM\n0,120\n\n# Speed\n0,1,0\n\n# Note\n0,100,10\n";
+        let (_, report) = Chart::from_official_reader_with_options(text.as_bytes(), &ParseOptions::strict()).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.ignored.len(), 3);
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        let cases: [(&str, usize, usize); 4] = [
+            ("# Version 2\n# BPM\n0,NaN\n# Speed\n0,1,0\n# Note\n0,100,10\n", 3, 3),
+            ("# Version 2\n# BPM\n0,120\n# Speed\ninf,1,0\n# Note\n0,100,10\n", 5, 1),
+            ("# Version 2\n# BPM\n0,120\n# Speed\n0,1,0\n# Note\n0,NaN,10\n", 7, 3),
+            ("# Version 2\n# BPM\n0,120\n# Speed\n0,1,0\n# Note\n0,100,1e39\n", 7, 7),
+        ];
+        for (text, line, column) in cases {
+            let error = Chart::from_official_reader(text.as_bytes()).err().unwrap();
+            assert!(matches!(error, ChartError::ParseNumber { line: l, column: c, .. } if l == line && c == column), "{}", error);
+            // 宽松模式跳过这一行，不会在排序时panic
+            let (_, report) = lenient(text);
+            assert_eq!(report.skipped_lines.len(), 1);
+            assert!(matches!(report.skipped_lines[0], ChartError::ParseNumber { line: l, .. } if l == line));
+        }
+        // 宽松模式修正时也不接受
+        let (_, report) = lenient("# Version 2\n# BPM\n0,120\n# Note\n0, NaN ,10\n");
+        assert!(matches!(report.skipped_lines[..], [ChartError::ParseNumber { line: 5, column: 3, .. }]));
+
+        // 直接构造的谱面里有NaN也不会在排序时panic
+        let mut data: ChartData = ChartData::new();
+        data.bpm = vec![BPM::new(f64::NAN, 120.0), BPM::new(0.0, 120.0)];
+        data.note = vec![Note::Tap(Tap::new(100.0, 0.0)), Note::Tap(Tap::new(f64::NAN, 0.0))];
+        Chart::new(data);
+    }
+
+    #[test]
+    fn non_finite_json_numbers_are_rejected() {
+        let json = |note: &str, speed: &str| format!("{{\"version\": 2, \"offset\": 0, \"bpm\": [{{\"time\": 0, \"bpm\": 120}}], \"speed\": [{}], \"note\": [{}]}}", speed, note);
+        let tap: &str = "{\"typename\": \"Tap\", \"time\": 100, \"degree\": 10}";
+        let speed: &str = "{\"time\": 0, \"speed\": 1, \"easing\": \"Step\"}";
+        assert!(Chart::from_json_reader(json(tap, speed).as_bytes()).is_ok());
+
+        // 超出f32范围的数读成f32会变成inf
+        let text: String = json("{\"typename\": \"Tap\", \"time\": 100, \"degree\": 1e39}", speed);
+        let error = Chart::from_json_reader(text.as_bytes()).err().unwrap();
+        assert!(matches!(&error, ChartError::ParseNumber { key, .. } if key == "note[0].degree"), "{}", error);
+        let (chart, report) = Chart::from_json_reader_with_options(text.as_bytes(), &ParseOptions::lenient()).unwrap();
+        assert!(chart.data().note.is_empty());
+        assert_eq!(report.skipped_lines.len(), 1);
+
+        let text: String = json(tap, "{\"time\": 0, \"speed\": -1e39, \"easing\": \"Step\"}");
+        let error = Chart::from_json_reader(text.as_bytes()).err().unwrap();
+        assert!(matches!(&error, ChartError::ParseNumber { key, .. } if key == "speed[0].speed"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_json_note_type_keeps_key() {
+        let text: &str = "{\"version\": 2, \"offset\": 0, \"bpm\": [], \"speed\": [], \"note\": [{\"typename\": \"Tap\", \"time\": 0, \"degree\": 0}, {\"typename\": \"Hold\", \"time\": 0, \"degree\": 0}]}";
+        let error = Chart::from_json_reader(text.as_bytes()).err().unwrap();
+        assert!(matches!(&error, ChartError::UnknownNoteType { key, .. } if key == "note[1].typename"), "{}", error);
+        assert_eq!(error.key(), Some("note[1].typename"));
+        assert_eq!(error.to_string(), format!("{}: note[1].typename: unknown note type `Hold`", error.path()));
+    }
+}
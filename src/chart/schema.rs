@@ -0,0 +1,213 @@
+// schema.rs
+// json谱面格式，导出用ChartJson，json schema也由它生成
+//
+// schema_version:
+//     1: 没有schema_version字段的旧版导出，note按type区分类型
+//     2: note按typename区分类型，加入speed和metadata
+//        time_signature是之后加入的可选字段，旧文件没有时按4/4拍
+//     3: speed的smooth换成easing
+//
+// 各版本导出的catch都带truedegree（判定时手机的实际角度），只给外部工具用，读取时忽略
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::bpm::BPM;
+use super::easing::Easing;
+use super::error::ChartError;
+use super::note::Note;
+use super::speed::Speed;
+use super::timesignature::TimeSignature;
+use super::speeddistance::SpeedDistance;
+use super::traildistance::TrailDistance;
+
+pub const SCHEMA_VERSION: i32 = 3;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ChartJson {
+    pub schema_version: i32,
+    pub version: i32,
+    pub offset: f64,
+    #[serde(default)]
+    pub metadata: Vec<String>,
+    pub bpm: Vec<BPM>,
+    #[serde(default)]
+    pub time_signature: Vec<TimeSignature>,
+    #[serde(default)]
+    pub speed: Vec<Speed>,
+    pub note: Vec<Note>,
+    // 以下两项是预处理的结果，只导出给外部工具用，读取时忽略
+    #[serde(default, skip_deserializing)]
+    pub speeddistance: Vec<SpeedDistance>,
+    #[serde(default, skip_deserializing)]
+    pub traildistance: Vec<TrailDistance>,
+}
+
+// 把旧版本的json升级到当前版本，返回原来的版本号
+pub fn migrate(json: &mut Value, path: &str) -> Result<i32, ChartError> {
+    let from: i32 = json.get("schema_version").and_then(Value::as_i64).unwrap_or(1) as i32;
+    if from > SCHEMA_VERSION {
+        return Err(ChartError::Json {
+            path: path.to_string(),
+            line: 0,
+            column: 0,
+            message: format!("unsupported schema_version {}, newest supported is {}", from, SCHEMA_VERSION),
+        });
+    }
+
+    if from < 2 {
+        // v1的note可能只有type没有typename
+        if let Some(notes) = json.get_mut("note").and_then(Value::as_array_mut) {
+            for note in notes.iter_mut() {
+                if note.get("typename").is_some() {
+                    continue;
+                }
+                let typename = note.get("type").and_then(Value::as_i64).and_then(|t| Note::typename_from_type(t as i32));
+                if let (Some(typename), Some(object)) = (typename, note.as_object_mut()) {
+                    object.insert("typename".to_string(), Value::from(typename));
+                }
+            }
+        }
+    }
+
+    if from < 3 {
+        // v2及以前的speed用smooth表示，0和1以外的值以前都按0处理
+        if let Some(speeds) = json.get_mut("speed").and_then(Value::as_array_mut) {
+            for speed in speeds.iter_mut() {
+                let object = match speed.as_object_mut() {
+                    Some(object) => object,
+                    None => continue,
+                };
+                if let Some(smooth) = object.remove("smooth") {
+                    let easing: Easing = smooth.as_i64().and_then(|code| Easing::from_code(code as i32)).filter(Easing::is_official).unwrap_or(Easing::Step);
+                    object.entry("easing").or_insert(serde_json::to_value(easing).unwrap());
+                }
+            }
+        }
+    }
+
+    if let Some(object) = json.as_object_mut() {
+        object.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    }
+    Ok(from)
+}
+
+// 生成ChartJson的json schema，仓库里的schema/chart.schema.json就是它的输出
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(ChartJson);
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::parse::ParseOptions;
+    use crate::chart::note::{Tap, Flick, Slide, SlideType, Rotate, Catch, Trail, Bomb};
+
+    fn sample() -> ChartData {
+        let mut data: ChartData = ChartData::new();
+        data.version = 2;
+        data.offset = -25.5;
+        data.metadata = vec!["# Charter someone".to_string()];
+        data.bpm = vec![BPM::new(0.0, 120.0), BPM::new(4000.0, 180.5)];
+        data.time_signature = vec![TimeSignature::new(0.0, 4, 4), TimeSignature::new(4000.0, 7, 8)];
+        data.speed = vec![
+            Speed::new(0.0, 1.0, Easing::Step),
+            Speed::new(1000.0, 2.5, Easing::Linear),
+            Speed::new(2000.0, 0.5, Easing::SineInOut),
+            Speed::new(3000.0, -1.0, Easing::Bezier { x1: 0.25, y1: 0.1, x2: 0.25, y2: 1.0 }),
+        ];
+        data.note = vec![
+            Note::Tap(Tap::new(100.0, 10.0)),
+            Note::Flick(Flick::new(200.0, 45.5)),
+            Note::Slide(Slide::new(300.0, 30.0, SlideType::Big, 90.0, 4, 8, 10.0, 20.0)),
+            Note::Rotate(Rotate::new(400.0, 10.0, -90.0, 0.0, 30.0)),
+            Note::Catch(Catch::new(500.0, 1.0)),
+            Note::Trail(Trail::new(600.0, 180.0, 0.0, 30.0, 30.0)),
+            Note::Bomb(Bomb::new(700.0, 270.0)),
+        ];
+        data
+    }
+
+    fn write_json(chart: &Chart) -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        chart.write_json(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn write_txt(chart: &Chart) -> String {
+        chart.to_string()
+    }
+
+    #[test]
+    fn json_round_trip_keeps_everything() {
+        let mut chart: Chart = Chart::new(sample());
+        chart.compute_note_beats(16);
+        let json: String = write_json(&chart);
+        let (loaded, report) = Chart::from_json_reader_with_options(json.as_bytes(), &ParseOptions::strict()).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(write_json(&loaded), json);
+        assert_eq!(write_txt(&loaded), write_txt(&chart));
+
+        let data: &ChartData = loaded.data();
+        assert_eq!(data.offset, -25.5);
+        assert_eq!(data.metadata, vec!["# Charter someone".to_string()]);
+        assert_eq!(data.time_signature.len(), 2);
+        let easings: Vec<Easing> = data.speed.iter().map(|speed| speed.easing).collect();
+        assert_eq!(easings, sample().speed.iter().map(|speed| speed.easing).collect::<Vec<Easing>>());
+        let typenames: Vec<&str> = data.note.iter().map(Note::typename).collect();
+        assert_eq!(typenames, vec!["Tap", "Flick", "Slide", "Rotate", "Catch", "Trail", "Bomb"]);
+        assert!(data.note.iter().all(|note| note.get_beat().is_some()));
+    }
+
+    #[test]
+    fn txt_to_json_to_txt() {
+        let chart: Chart = Chart::new(sample());
+        let txt: String = write_txt(&chart);
+        let from_json: Chart = Chart::from_json_reader(write_json(&chart).as_bytes()).unwrap();
+        assert_eq!(write_txt(&from_json), txt);
+    }
+
+    #[test]
+    fn catch_true_degree_is_exported() {
+        let chart: Chart = Chart::new(sample());
+        let value: Value = serde_json::from_str(&write_json(&chart)).unwrap();
+        let catch: &Value = value["note"].as_array().unwrap().iter()
+            .find(|note| note["typename"] == "Catch").unwrap();
+        let expected: f32 = chart.find_degree_by_time(500.0);
+        assert_eq!(catch["truedegree"].as_f64().unwrap() as f32, expected);
+        // 读回来时忽略，不会写进谱面数据
+        let loaded: Chart = Chart::from_json_reader(write_json(&chart).as_bytes()).unwrap();
+        match &loaded.data().note[4] {
+            Note::Catch(catch) => assert!(catch.truedegree.is_none()),
+            _ => panic!("note 4 should be a catch"),
+        }
+    }
+
+    #[test]
+    fn exported_notes_match_schema() {
+        let chart: Chart = Chart::new(sample());
+        let value: Value = serde_json::from_str(&write_json(&chart)).unwrap();
+        let schema: Value = serde_json::from_str(&json_schema()).unwrap();
+        let variants: &Vec<Value> = schema["definitions"]["Note"]["oneOf"].as_array().unwrap();
+        for note in value["note"].as_array().unwrap() {
+            let typename: &Value = &note["typename"];
+            let variant: &Value = variants.iter().find(|variant| variant["properties"]["typename"]["enum"][0] == *typename).unwrap();
+            for key in note.as_object().unwrap().keys() {
+                assert!(variant["properties"].get(key).is_some(), "{} writes `{}` which is not in the schema", typename, key);
+            }
+            for key in variant["required"].as_array().unwrap() {
+                assert!(note.get(key.as_str().unwrap()).is_some(), "{} misses required `{}`", typename, key);
+            }
+        }
+    }
+
+    #[test]
+    fn committed_schema_is_up_to_date() {
+        let generated: Value = serde_json::from_str(&json_schema()).unwrap();
+        let committed: Value = serde_json::from_str(include_str!("../../schema/chart.schema.json")).unwrap();
+        assert!(generated == committed, "schema/chart.schema.json is out of date, regenerate it with json_schema()");
+    }
+}
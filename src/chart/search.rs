@@ -0,0 +1,179 @@
+// search.rs
+// 在按时间（或者按距离）排好序的数组里二分查找下标
+//
+// 有多个元素和要找的值相等时：
+//     LastAtOrBefore 返回相等的最后一个
+//     FirstAtOrAfter 返回相等的第一个
+//     LastBefore和FirstAfter跳过所有相等的元素
+// 找不到时返回None，比如要找的值比第一个元素还小时LastAtOrBefore和LastBefore都是None
+use super::bpm::BPM;
+use super::speed::Speed;
+use super::note::Note;
+use super::speeddistance::SpeedDistance;
+use super::traildistance::TrailDistance;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    LastAtOrBefore, // 小于等于的最大值
+    FirstAtOrAfter, // 大于等于的最小值
+    LastBefore, // 小于的最大值
+    FirstAfter, // 大于的最小值
+}
+
+pub trait DistanceGetter {
+    fn get_distance(&self) -> f64;
+}
+
+impl DistanceGetter for SpeedDistance {
+    fn get_distance(&self) -> f64 {
+        self.distance
+    }
+}
+
+impl DistanceGetter for TrailDistance {
+    fn get_distance(&self) -> f64 {
+        self.distance
+    }
+}
+
+pub trait TimeGetter {
+    fn get_time(&self) -> f64;
+}
+
+impl TimeGetter for TrailDistance {
+    fn get_time(&self) -> f64 {
+        self.time
+    }
+}
+
+impl TimeGetter for SpeedDistance {
+    fn get_time(&self) -> f64 {
+        self.time
+    }
+}
+
+impl TimeGetter for Speed {
+    fn get_time(&self) -> f64 {
+        self.time
+    }
+}
+
+impl TimeGetter for Note {
+    fn get_time(&self) -> f64 {
+        self.get_time()
+    }
+}
+
+impl TimeGetter for BPM {
+    fn get_time(&self) -> f64 {
+        self.time
+    }
+}
+
+// input需要按key从小到大排好序
+pub fn find_pos_by_key<T, F: Fn(&T) -> f64>(input: &[T], key: F, target: f64, mode: SearchMode) -> Option<usize> {
+    match mode {
+        SearchMode::LastAtOrBefore => input.partition_point(|item| key(item) <= target).checked_sub(1),
+        SearchMode::LastBefore => input.partition_point(|item| key(item) < target).checked_sub(1),
+        SearchMode::FirstAtOrAfter => Some(input.partition_point(|item| key(item) < target)).filter(|&i| i < input.len()),
+        SearchMode::FirstAfter => Some(input.partition_point(|item| key(item) <= target)).filter(|&i| i < input.len()),
+    }
+}
+
+// 这个函数的作用是根据时间找到对应的数组下标，input需要按时间排好序
+pub fn find_pos_by_time<T: TimeGetter>(input: &[T], time: f64, mode: SearchMode) -> Option<usize> {
+    find_pos_by_key(input, T::get_time, time, mode)
+}
+
+// 这个函数的作用是根据积分找到对应的数组下标，input需要按距离排好序
+pub fn find_pos_by_distance<T: DistanceGetter>(input: &[T], distance: f64, mode: SearchMode) -> Option<usize> {
+    find_pos_by_key(input, T::get_distance, distance, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::easing::Easing;
+
+    const MODES: [SearchMode; 4] = [SearchMode::LastAtOrBefore, SearchMode::FirstAtOrAfter, SearchMode::LastBefore, SearchMode::FirstAfter];
+
+    // 逐个比较的参考实现
+    fn linear(input: &[f64], target: f64, mode: SearchMode) -> Option<usize> {
+        match mode {
+            SearchMode::LastAtOrBefore => (0..input.len()).rev().find(|&i| input[i] <= target),
+            SearchMode::LastBefore => (0..input.len()).rev().find(|&i| input[i] < target),
+            SearchMode::FirstAtOrAfter => (0..input.len()).find(|&i| input[i] >= target),
+            SearchMode::FirstAfter => (0..input.len()).find(|&i| input[i] > target),
+        }
+    }
+
+    // 长度0到6、元素取自{0, 1, 2}的所有有序数组
+    fn sorted_arrays() -> Vec<Vec<f64>> {
+        let mut arrays: Vec<Vec<f64>> = vec![Vec::new()];
+        let mut start: usize = 0;
+        for _ in 0..6 {
+            let end: usize = arrays.len();
+            for i in start..end {
+                let low: f64 = arrays[i].last().copied().unwrap_or(0.0);
+                for value in [0.0, 1.0, 2.0] {
+                    if value >= low {
+                        let mut array: Vec<f64> = arrays[i].clone();
+                        array.push(value);
+                        arrays.push(array);
+                    }
+                }
+            }
+            start = end;
+        }
+        arrays
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let targets: [f64; 9] = [-1.0, -0.5, 0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0];
+        let arrays: Vec<Vec<f64>> = sorted_arrays();
+        assert_eq!(arrays.len(), 84);
+        for array in &arrays {
+            for &target in &targets {
+                for mode in MODES {
+                    assert_eq!(find_pos_by_key(array, |&value| value, target, mode), linear(array, target, mode), "{:?} {} {:?}", array, target, mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        let key = |value: &f64| *value;
+        // 空数组
+        for mode in MODES {
+            assert_eq!(find_pos_by_key(&[], key, 0.0, mode), None);
+        }
+        // 只有一个元素
+        assert_eq!(find_pos_by_key(&[1.0], key, 1.0, SearchMode::LastAtOrBefore), Some(0));
+        assert_eq!(find_pos_by_key(&[1.0], key, 1.0, SearchMode::FirstAtOrAfter), Some(0));
+        assert_eq!(find_pos_by_key(&[1.0], key, 1.0, SearchMode::LastBefore), None);
+        assert_eq!(find_pos_by_key(&[1.0], key, 1.0, SearchMode::FirstAfter), None);
+        // 相等的元素
+        let input: [f64; 5] = [0.0, 1.0, 1.0, 1.0, 2.0];
+        assert_eq!(find_pos_by_key(&input, key, 1.0, SearchMode::LastAtOrBefore), Some(3));
+        assert_eq!(find_pos_by_key(&input, key, 1.0, SearchMode::FirstAtOrAfter), Some(1));
+        assert_eq!(find_pos_by_key(&input, key, 1.0, SearchMode::LastBefore), Some(0));
+        assert_eq!(find_pos_by_key(&input, key, 1.0, SearchMode::FirstAfter), Some(4));
+        // 比第一个还小、比最后一个还大
+        assert_eq!(find_pos_by_key(&input, key, -1.0, SearchMode::LastAtOrBefore), None);
+        assert_eq!(find_pos_by_key(&input, key, -1.0, SearchMode::FirstAtOrAfter), Some(0));
+        assert_eq!(find_pos_by_key(&input, key, 3.0, SearchMode::LastBefore), Some(4));
+        assert_eq!(find_pos_by_key(&input, key, 3.0, SearchMode::FirstAfter), None);
+    }
+
+    #[test]
+    fn time_and_distance_getters() {
+        let speed: Vec<Speed> = [0.0, 100.0, 100.0, 300.0].iter().map(|&time| Speed::new(time, 1.0, Easing::Step)).collect();
+        assert_eq!(find_pos_by_time(&speed, 100.0, SearchMode::LastAtOrBefore), Some(2));
+        assert_eq!(find_pos_by_time(&speed, 200.0, SearchMode::FirstAfter), Some(3));
+        let distance: Vec<SpeedDistance> = [0.0, 50.0, 80.0].iter().map(|&distance| SpeedDistance::new(0.0, 1.0, Easing::Step, distance)).collect();
+        assert_eq!(find_pos_by_distance(&distance, 60.0, SearchMode::LastBefore), Some(1));
+        assert_eq!(find_pos_by_distance(&distance, 80.0, SearchMode::FirstAtOrAfter), Some(2));
+    }
+}
@@ -1,15 +1,18 @@
 // speed.rs
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+use super::easing::Easing;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
 pub struct Speed {
-    pub time: f32,
+    pub time: f64,
     pub speed: f32,
-    pub smooth: i32,
+    pub easing: Easing, // 从上一个速度变到这个速度的方式，对应官谱的smooth
 }
 
 impl Speed {
-    pub fn new(time: f32, speed: f32, smooth: i32) -> Self {
-        Self { time, speed, smooth}
+    pub fn new(time: f64, speed: f32, easing: Easing) -> Self {
+        Self { time, speed, easing }
     }
 }
\ No newline at end of file
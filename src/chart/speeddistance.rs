@@ -1,16 +1,19 @@
 // speed.rs
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+use super::easing::Easing;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct SpeedDistance {
-    pub time: f32,
+    pub time: f64,
     pub speed: f32,
-    pub smooth: i32,
-    pub distance: f32,
+    pub easing: Easing,
+    pub distance: f64,
 }
 
 impl SpeedDistance {
-    pub fn new(time: f32, speed: f32, smooth: i32, distance: f32) -> Self {
-        Self { time, speed, smooth, distance }
+    pub fn new(time: f64, speed: f32, easing: Easing, distance: f64) -> Self {
+        Self { time, speed, easing, distance }
     }
 }
\ No newline at end of file
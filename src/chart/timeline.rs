@@ -0,0 +1,1036 @@
+// timeline.rs
+// 由ChartData算出来的派生数据：bpm和拍号的时间表、速度积分、trail的位置、手机角度
+// 只能用ChartTimeline::new从谱面数据整个生成（或者用rebuild_from增量生成），生成之后不能修改
+// 谱面数据改了就重新生成，这样缓存不会和谱面对不上
+//
+// 时间、拍数、距离、角度之间的换算都在这里
+use std::ops::Range;
+
+use super::chartdata::ChartData;
+use super::search::{SearchMode, find_pos_by_time};
+use super::trailcurve::{CurveCache, TrailCurve, TrailSegment};
+use super::bezier::{self, BezierCurve};
+use super::timing::{BpmTiming, MeterTiming};
+use super::speed::Speed;
+use super::easing::Easing;
+use super::note::Note;
+use super::beat::{BeatPosition, MeasurePosition};
+use super::speeddistance::{self, SpeedDistance};
+use super::traildistance::TrailDistance;
+
+#[derive(Clone)]
+pub struct ChartTimeline {
+    bpm_timing: BpmTiming,
+    meter_timing: MeterTiming,
+    speed_distance: Vec<SpeedDistance>,
+    speed_distance_plain: Vec<SpeedDistance>, // 没有变速时的速度积分
+    trail_distance: Vec<TrailDistance>,
+    trail_distance_plain: Vec<TrailDistance>, // 没有变速时trail的位置
+    trail_by_time: Vec<TrailDistance>, // trail_distance按时间排序，画trail时两两相连
+    phone_trail_distance: Vec<TrailDistance>,
+    trail_curves: Vec<BezierCurve>, // trail_distance每一段的曲线，带查找表
+    phone_curves: Vec<BezierCurve>, // phone_trail_distance每一段的曲线
+    curve_cache: CurveCache, // 建过的曲线，增量重建时不用重新建表
+    note_end_time: Vec<f64>, // 每个note结束的时间，slide要加上持续时间
+    note_end_max: Vec<f64>, // note_end_time的前缀最大值，用来二分
+}
+
+impl Default for ChartTimeline {
+    fn default() -> Self {
+        Self::new(&ChartData::default())
+    }
+}
+
+// 解a*x^2 + b*x + c = 0，返回从小到大排列的实数根
+// a和b都为0时，c也为0就返回0（任意x都是解），否则无解
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return if c == 0.0 { vec![0.0] } else { Vec::new() };
+        }
+        return vec![-c / b];
+    }
+    let mut discriminant: f64 = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        // 相切时浮点误差可能让判别式略小于0
+        if discriminant > -1e-9 * b * b {
+            discriminant = 0.0;
+        } else {
+            return Vec::new();
+        }
+    }
+    // 避免b和根号相减时损失精度
+    let q: f64 = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    let mut roots: Vec<f64> = vec![q / a, c / q];
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots.dedup();
+    roots
+}
+
+// 在[0, length)上找f的根
+// 把区间分成小段，找到变号的地方二分，同一小段里有两个根时会漏掉
+fn find_roots<F: Fn(f64) -> f64>(f: F, length: f64) -> Vec<f64> {
+    let steps: usize = 64;
+    let mut roots: Vec<f64> = Vec::new();
+    for i in 0..steps {
+        let (mut low, mut high): (f64, f64) = (length * i as f64 / steps as f64, length * (i + 1) as f64 / steps as f64);
+        let (f_low, f_high): (f64, f64) = (f(low), f(high));
+        if f_low == 0.0 {
+            roots.push(low);
+            continue;
+        }
+        if f_low.signum() == f_high.signum() || f_high == 0.0 {
+            continue;
+        }
+        for _ in 0..60 {
+            let mid: f64 = (low + high) / 2.0;
+            if f(mid).signum() == f_low.signum() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        roots.push((low + high) / 2.0);
+    }
+    roots
+}
+
+// trail按距离排序，距离相同的按时间排序
+fn by_distance(a: &TrailDistance, b: &TrailDistance) -> std::cmp::Ordering {
+    a.distance.total_cmp(&b.distance).then(a.time.total_cmp(&b.time))
+}
+
+// points从第keep个开始是新加的点，排在前面的点后面，只排序新加的点
+fn sort_points(points: &mut [TrailDistance], keep: usize) {
+    points[keep..].sort_by(by_distance);
+}
+
+// 从第keep - 1段开始重建曲线，前面的段两头的点都没变
+fn build_curves_from(points: &[TrailDistance], curves: &mut Vec<BezierCurve>, keep: usize, cache: &mut CurveCache) {
+    let from: usize = keep.saturating_sub(1).min(curves.len());
+    curves.truncate(from);
+    curves.extend(TrailCurve::build_curves_cached(&points[from..], cache));
+}
+
+// 时间最晚的点，keep之后是新加的点，时间都比keep之前的点晚
+// sorted为true时keep之前的点按时间排好序，最晚的就是最后一个
+fn latest(points: &[TrailDistance], keep: usize, sorted: bool) -> TrailDistance {
+    let from: usize = if keep < points.len() {
+        keep
+    } else if sorted {
+        keep.saturating_sub(1)
+    } else {
+        0
+    };
+    *points[from..].iter().max_by(|a, b| a.time.total_cmp(&b.time)).unwrap()
+}
+
+impl ChartTimeline {
+    // data需要已经排好序（ChartData::sort）
+    pub fn new(data: &ChartData) -> Self {
+        let mut timeline: ChartTimeline = Self::with_timing(data);
+        timeline.push_speed_distance(&data.speed, 0);
+        // 第一个速度事件在0之前时开头补的点会排在它后面
+        timeline.speed_distance.sort_by(|a: &SpeedDistance, b: &SpeedDistance| a.time.total_cmp(&b.time));
+        timeline.build_trails(data, 0, f64::MIN);
+        timeline.build_note_end(&data.note, f64::MIN);
+        timeline
+    }
+
+    // 只有bpm和拍号时间表，其他缓存都是空的
+    fn with_timing(data: &ChartData) -> Self {
+        let bpm_timing: BpmTiming = BpmTiming::new(&data.bpm);
+        let meter_timing: MeterTiming = MeterTiming::new(&data.time_signature, &bpm_timing);
+        Self { bpm_timing, meter_timing, ..Self::empty() }
+    }
+
+    fn empty() -> Self {
+        Self {
+            bpm_timing: BpmTiming::default(),
+            meter_timing: MeterTiming::default(),
+            speed_distance: Vec::new(),
+            speed_distance_plain: Vec::new(),
+            trail_distance: Vec::new(),
+            trail_distance_plain: Vec::new(),
+            trail_by_time: Vec::new(),
+            phone_trail_distance: Vec::new(),
+            trail_curves: Vec::new(),
+            phone_curves: Vec::new(),
+            curve_cache: CurveCache::new(),
+            note_end_time: Vec::new(),
+            note_end_max: Vec::new(),
+        }
+    }
+
+    // 速度都不为负时距离跟着时间走，trail按距离排好序也就是按时间排好序
+    // 贝塞尔缓动的控制点在0到1之外时中间的速度可能超出两头，也算不上
+    fn is_forward(&self) -> bool {
+        self.speed_distance.iter().all(|speed_distance| speed_distance.speed >= 0.0 && match speed_distance.easing {
+            Easing::Bezier { y1, y2, .. } => (0.0..=1.0).contains(&y1) && (0.0..=1.0).contains(&y2),
+            _ => true,
+        })
+    }
+
+    // 从第note_index个note开始重新算trail、手机角度、没有变速时的trail和它们的曲线，时间在boundary之前的点保留
+    // 完整生成时note_index为0，boundary为f64::MIN
+    // 距离跟着时间走时保留的点正好是开头的一段，新加的点都排在后面，只需要处理末尾
+    // 有倒流时保留的点散在各处，只能整个重新排序
+    fn build_trails(&mut self, data: &ChartData, note_index: usize, boundary: f64) {
+        let forward: bool = self.is_forward();
+        let keep: usize = if forward {
+            let keep: usize = self.trail_distance.partition_point(|trail_distance| trail_distance.time < boundary);
+            self.trail_distance.truncate(keep);
+            keep
+        } else {
+            self.trail_distance.retain(|trail_distance| trail_distance.time < boundary);
+            0
+        };
+        self.push_trail_distance(&data.note, note_index, forward);
+        sort_points(&mut self.trail_distance, keep);
+        build_curves_from(&self.trail_distance, &mut self.trail_curves, keep, &mut self.curve_cache);
+
+        // 手机角度要用find_degree_by_time，trail要先算好
+        let phone_keep: usize = if forward {
+            let keep: usize = self.phone_trail_distance.partition_point(|trail_distance| trail_distance.time < boundary);
+            self.phone_trail_distance.truncate(keep);
+            keep
+        } else {
+            self.phone_trail_distance.retain(|trail_distance| trail_distance.time < boundary);
+            0
+        };
+        self.push_phone_trail_distance(&data.note, note_index, forward);
+        // catch和rotate前后的点不一定按时间先后生成，倒流时距离也不跟着时间走
+        // find_phone_degree_by_time按距离二分，这里要重新排好序
+        sort_points(&mut self.phone_trail_distance, phone_keep);
+        build_curves_from(&self.phone_trail_distance, &mut self.phone_curves, phone_keep, &mut self.curve_cache);
+
+        // trail_distance已经按时间排好序时直接接上末尾
+        if forward {
+            self.trail_by_time.truncate(keep);
+            self.trail_by_time.extend_from_slice(&self.trail_distance[keep..]);
+        } else {
+            self.trail_by_time = self.trail_distance.clone();
+            self.trail_by_time.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+
+        self.build_plain(data, note_index, boundary);
+    }
+
+    // 没有变速时距离就是时间，只需要重新算trail的位置，不用复制整个谱面
+    fn build_plain(&mut self, data: &ChartData, note_index: usize, boundary: f64) {
+        let mut plain: ChartTimeline = Self::empty();
+        plain.push_speed_distance(&[Speed::new(0.0, 1.0, Easing::Step)], 0);
+        plain.trail_distance = std::mem::take(&mut self.trail_distance_plain);
+        let keep: usize = plain.trail_distance.partition_point(|trail_distance| trail_distance.time < boundary);
+        plain.trail_distance.truncate(keep);
+        plain.push_trail_distance(&data.note, note_index, true);
+        sort_points(&mut plain.trail_distance, keep);
+        self.speed_distance_plain = plain.speed_distance;
+        self.trail_distance_plain = plain.trail_distance;
+    }
+
+    // from_time之前结束的note不受影响，从第一个结束时间不早于from_time的note开始重算
+    // 完整生成时from_time为f64::MIN
+    fn build_note_end(&mut self, note: &[Note], from_time: f64) {
+        let keep: usize = self.note_end_max.partition_point(|&time| time < from_time).min(note.len());
+        self.note_end_time.truncate(keep);
+        self.note_end_max.truncate(keep);
+        let mut max: f64 = self.note_end_max.last().copied().unwrap_or(f64::MIN);
+        for note in note[keep..].iter() {
+            let time: f64 = self.note_end(note);
+            max = max.max(time);
+            self.note_end_time.push(time);
+            self.note_end_max.push(max);
+        }
+    }
+
+    // 这个函数的作用是算出note结束的时间，slide持续amount / snap拍，其他note没有持续时间
+    pub fn note_end(&self, note: &Note) -> f64 {
+        match note {
+            Note::Slide(slide) if slide.snap != 0 => {
+                let beat: f64 = self.chart_time_to_beat(slide.time) + slide.amount as f64 / slide.snap as f64;
+                self.beat_to_chart_time(beat).max(slide.time)
+            },
+            _ => note.get_time(),
+        }
+    }
+
+    // 这个函数的作用是只重新计算from_time之后受影响的部分，编辑器里拖动时用
+    // self必须是由改动前的谱面生成的，并且from_time之前的bpm、speed、note都没有改动，否则结果不对
+    // 算不了增量的时候（改动在开头附近等）退回到ChartTimeline::new
+    pub fn rebuild_from(mut self, data: &ChartData, from_time: f64) -> Self {
+        if self.speed_distance.is_empty() || self.trail_distance.is_empty() {
+            return Self::new(data);
+        }
+        let is_trail = |note: &Note| matches!(note, Note::Trail(_) | Note::Rotate(_));
+        let timing: ChartTimeline = Self::with_timing(data);
+        self.bpm_timing = timing.bpm_timing;
+        self.meter_timing = timing.meter_timing;
+
+        // 速度积分：保留from_time之前的点，从第一个在from_time之后的速度事件接着算
+        // 这个事件的缓动从上一个事件就开始了，所以note要从上一个事件的时间开始重算
+        let speed_index: usize = data.speed.partition_point(|speed| speed.time < from_time);
+        let keep: usize = self.speed_distance.partition_point(|speed_distance| speed_distance.time < from_time);
+        let from_time: f64 = match speed_index {
+            0 => from_time,
+            _ => from_time.min(data.speed[speed_index - 1].time),
+        };
+
+        // note：trail的角度要在相邻两个trail之间插值，所以从from_time之前最后一个trail之后开始重算
+        // 手机角度会往后看100ms内的catch
+        let last_trail: Option<usize> = data.note.iter().rposition(|note| note.get_time() < from_time && is_trail(note));
+        let mut note_index: usize = match last_trail {
+            Some(index) => (index + 1).min(data.note.partition_point(|note| note.get_time() < from_time - 100.0)),
+            None => 0,
+        };
+        // rotate会往前后各加30ms的点，catch的点可能属于前面的note
+        // 往前找一个位置，保证前后两部分note生成的点在时间上不交叉，这样才能按时间把旧的点去掉
+        let mut boundary: f64 = f64::MIN;
+        while note_index > 0 {
+            // trail_distance的点就在note的时间上，一起算进去
+            let entry_time = |i: usize| self.phone_trail_entries(&data.note, i).into_iter().map(|entry| entry.time).chain(std::iter::once(data.note[i].get_time()));
+            let split: f64 = data.note[note_index - 1].get_time();
+            let before: f64 = (0..note_index).rev()
+                .take_while(|&i| data.note[i].get_time() >= split - 200.0)
+                .flat_map(entry_time)
+                .fold(split, f64::max);
+            let after: Option<f64> = (note_index..data.note.len())
+                .take_while(|&i| data.note[i].get_time() <= split + 200.0)
+                .flat_map(entry_time)
+                .reduce(f64::min);
+            match after {
+                Some(after) if before < after => {
+                    boundary = (before + after) / 2.0;
+                    break;
+                },
+                // 后面200ms内没有note，再往后的点都比before晚
+                None => {
+                    boundary = before + 1.0;
+                    break;
+                },
+                _ => note_index -= 1,
+            }
+        }
+
+        if speed_index == 0 || note_index == 0 || boundary <= 0.0 || !data.note[..note_index].iter().any(is_trail) {
+            return Self::new(data);
+        }
+
+        self.speed_distance.truncate(keep);
+        self.push_speed_distance(&data.speed, speed_index);
+
+        // 结尾补的点时间都比boundary大，会一起被去掉
+        self.build_trails(data, note_index, boundary);
+        self.build_note_end(&data.note, from_time);
+        self
+    }
+
+    pub fn bpm_timing(&self) -> &BpmTiming {
+        &self.bpm_timing
+    }
+
+    pub fn meter_timing(&self) -> &MeterTiming {
+        &self.meter_timing
+    }
+
+    pub fn speed_distance(&self) -> &[SpeedDistance] {
+        &self.speed_distance
+    }
+
+    pub fn speed_distance_plain(&self) -> &[SpeedDistance] {
+        &self.speed_distance_plain
+    }
+
+    pub fn trail_distance(&self) -> &[TrailDistance] {
+        &self.trail_distance
+    }
+
+    pub fn trail_distance_plain(&self) -> &[TrailDistance] {
+        &self.trail_distance_plain
+    }
+
+    pub fn phone_trail_distance(&self) -> &[TrailDistance] {
+        &self.phone_trail_distance
+    }
+
+    pub fn trail_by_time(&self) -> &[TrailDistance] {
+        &self.trail_by_time
+    }
+
+    // 这个函数的作用是找到和[start, end]有重叠的trail段，第i段是trail_by_time的第i个点到第i + 1个点
+    pub fn trail_segments_in_time_range(&self, start: f64, end: f64) -> Range<usize> {
+        let trails: &[TrailDistance] = &self.trail_by_time;
+        let first: usize = trails.partition_point(|trail| trail.time < start).saturating_sub(1);
+        let last: usize = trails.partition_point(|trail| trail.time <= end).min(trails.len().saturating_sub(1));
+        first..last.max(first)
+    }
+
+    pub fn note_end_time(&self) -> &[f64] {
+        &self.note_end_time
+    }
+
+    // 这个函数的作用是找到和[start, end]有重叠的note的下标，按时间排序
+    // note是生成这个timeline的ChartData::note
+    // 开始的位置用结束时间的前缀最大值二分，前面有很长的slide时中间已经结束的note要再跳过
+    pub fn note_indices_in_time_range<'a>(&'a self, note: &'a [Note], start: f64, end: f64) -> impl Iterator<Item = usize> + 'a {
+        let first: usize = self.note_end_max.partition_point(|&time| time < start);
+        let last: usize = note.partition_point(|note| note.get_time() <= end);
+        (first..last).filter(move |&i| self.note_end_time[i] >= start)
+    }
+
+    // 这个函数的作用是找到距离在[start, end]内的所有时间段，按时间排序，两头可能是无穷
+    // 倒流时距离会来回走，同一段距离可能对应好几个时间段
+    pub fn time_ranges_by_distance(&self, start: f64, end: f64) -> Vec<(f64, f64)> {
+        let mut ranges: Vec<(f64, f64)> = Vec::new();
+        if start > end {
+            return ranges;
+        }
+        let mut times: Vec<f64> = self.find_times_by_distance(start);
+        times.extend(self.find_times_by_distance(end));
+        times.sort_by(|a, b| a.total_cmp(b));
+        times.dedup();
+
+        // 相邻两个交点之间的距离要么都在范围内，要么都不在，取中间一点判断
+        let inside = |time: f64| {
+            let distance: f64 = self.find_distance_by_time(time);
+            distance >= start && distance <= end
+        };
+        let mut bounds: Vec<f64> = vec![f64::NEG_INFINITY];
+        bounds.extend(times.iter());
+        bounds.push(f64::INFINITY);
+        for pair in bounds.windows(2) {
+            let (a, b): (f64, f64) = (pair[0], pair[1]);
+            let mid: f64 = match (a.is_finite(), b.is_finite()) {
+                (true, true) => (a + b) / 2.0,
+                (true, false) => a + 1.0,
+                (false, true) => b - 1.0,
+                (false, false) => 0.0,
+            };
+            if !inside(mid) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.1 == a => last.1 = b,
+                _ => ranges.push((a, b)),
+            }
+        }
+
+        // 只在一瞬间碰到边界的交点（比如正好在边界上掉头）
+        for time in times {
+            if !ranges.iter().any(|&(a, b)| a <= time && time <= b) {
+                let index: usize = ranges.partition_point(|&(a, _)| a < time);
+                ranges.insert(index, (time, time));
+            }
+        }
+        ranges
+    }
+
+    // 这个函数的作用是根据谱面时间计算拍数，规则见timing.rs
+    pub fn chart_time_to_beat(&self, chart_time: f64) -> f64 {
+        self.bpm_timing.time_to_beat(chart_time)
+    }
+
+    // 这个函数的作用是根据拍数计算谱面时间
+    pub fn beat_to_chart_time(&self, beats: f64) -> f64 {
+        self.bpm_timing.beat_to_time(beats)
+    }
+
+    // 这个函数的作用是根据拍数计算小节数，小数部分是小节内的位置，规则见timing.rs
+    pub fn beat_to_measure(&self, beat: f64) -> f64 {
+        self.meter_timing.beat_to_measure(beat)
+    }
+
+    // 这个函数的作用是根据小节数计算拍数
+    pub fn measure_to_beat(&self, measure: f64) -> f64 {
+        self.meter_timing.measure_to_beat(measure)
+    }
+
+    // 这个函数的作用是根据谱面时间计算小节数
+    pub fn chart_time_to_measure(&self, chart_time: f64) -> f64 {
+        self.beat_to_measure(self.chart_time_to_beat(chart_time))
+    }
+
+    // 这个函数的作用是根据小节数计算谱面时间
+    pub fn measure_to_chart_time(&self, measure: f64) -> f64 {
+        self.beat_to_chart_time(self.measure_to_beat(measure))
+    }
+
+    // 这个函数的作用是找到谱面时间所在小节一小节有多少拍
+    pub fn find_beats_per_measure_by_time(&self, chart_time: f64) -> f64 {
+        self.meter_timing.segment_at_beat(self.chart_time_to_beat(chart_time)).beats_per_measure
+    }
+
+    // 这个函数的作用是把谱面时间转成拍数位置，分母不超过max_denominator
+    pub fn chart_time_to_beat_position(&self, chart_time: f64, max_denominator: i32) -> BeatPosition {
+        BeatPosition::from_beat(self.chart_time_to_beat(chart_time), max_denominator)
+    }
+
+    // 这个函数的作用是根据拍数位置计算谱面时间
+    pub fn beat_position_to_chart_time(&self, position: &BeatPosition) -> f64 {
+        self.beat_to_chart_time(position.to_beat())
+    }
+
+    // 这个函数的作用是把拍数位置换算成小节位置，小节内的分母不超过max_denominator
+    pub fn beat_position_to_measure_position(&self, position: &BeatPosition, max_denominator: i32) -> MeasurePosition {
+        MeasurePosition::from_measure(self.beat_to_measure(position.to_beat()), max_denominator)
+    }
+
+    // 这个函数的作用是把小节位置换算成拍数位置，分母不超过max_denominator
+    pub fn measure_position_to_beat_position(&self, position: &MeasurePosition, max_denominator: i32) -> BeatPosition {
+        BeatPosition::from_beat(self.measure_to_beat(position.to_measure()), max_denominator)
+    }
+
+    // 这个函数的作用是根据时间找到对应的速度，然后计算出积分
+    pub fn find_distance_by_time(&self, time: f64) -> f64 {
+        // 第一个点之前按第一个点的速度往前延伸
+        let p1: usize = find_pos_by_time(&self.speed_distance, time, SearchMode::LastAtOrBefore).unwrap_or(0);
+        let t1: f64 = self.speed_distance[p1].time;
+        let v1: f64 = self.speed_distance[p1].speed as f64;
+        let d1: f64 = self.speed_distance[p1].distance;
+        let p2: usize = p1 + 1;
+
+        if p2 < self.speed_distance.len() {
+            let t2: f64 = self.speed_distance[p2].time;
+            let v2: f64 = self.speed_distance[p2].speed as f64;
+            d1 + self.speed_distance[p2].easing.segment_distance(v1, v2, t2 - t1, time - t1)
+        } else {
+            d1 + (time - t1) * v1
+        }
+    }
+
+    pub fn find_bpm_by_time(&self, chart_time: f64) -> f64 {
+        self.bpm_timing.bpm_at(chart_time)
+    }
+
+    // 这个函数的作用是根据积分找到对应的时间，有多个解时返回最早的一个，需要所有解时用find_times_by_distance
+    // 到不了这个距离时返回距离最接近的速度事件的时间
+    pub fn find_time_by_distance(&self, distance: f64) -> f64 {
+        if let Some(time) = self.find_times_by_distance(distance).first() {
+            return *time;
+        }
+        self.speed_distance
+            .iter()
+            .min_by(|a, b| (a.distance - distance).abs().total_cmp(&(b.distance - distance).abs()))
+            .map_or(0.0, |speed_distance| speed_distance.time)
+    }
+
+    // 这个函数的作用是找到所有积分等于distance的时间，按时间从小到大排列
+    // 速度为负数时距离会往回走，同一个距离可能对应多个时间
+    // Step和Linear的一段距离是时间的一次或二次函数，直接解方程
+    // 其他缓动把这一段分成小段，找到变号的地方再二分
+    // 速度为0的一段如果正好停在distance上，只返回这一段的开始时间
+    pub fn find_times_by_distance(&self, distance: f64) -> Vec<f64> {
+        let mut times: Vec<f64> = Vec::new();
+        let list: &Vec<SpeedDistance> = &self.speed_distance;
+        if list.is_empty() {
+            return times;
+        }
+
+        // 第一个点之前按第一个点的速度往前延伸，和find_distance_by_time一致
+        let first_speed: f64 = list[0].speed as f64;
+        if first_speed != 0.0 {
+            let x: f64 = (distance - list[0].distance) / first_speed;
+            if x < 0.0 {
+                times.push(list[0].time + x);
+            }
+        }
+
+        for i in 0..list.len() {
+            let (t1, d1, v1): (f64, f64, f64) = (list[i].time, list[i].distance, list[i].speed as f64);
+            let (length, v2, easing): (f64, f64, Easing) = match list.get(i + 1) {
+                Some(next) if next.time <= t1 => continue, // 同一时间的多个事件，只看最后一个
+                Some(next) => (next.time - t1, next.speed as f64, next.easing),
+                None => (f64::INFINITY, v1, Easing::Step),
+            };
+            let roots: Vec<f64> = match easing {
+                Easing::Step => solve_quadratic(0.0, v1, d1 - distance),
+                Easing::Linear => solve_quadratic((v2 - v1) / length / 2.0, v1, d1 - distance),
+                _ => find_roots(|x| d1 + easing.segment_distance(v1, v2, length, x) - distance, length),
+            };
+            for x in roots {
+                if x >= 0.0 && x < length {
+                    times.push(t1 + x);
+                }
+            }
+        }
+        times
+    }
+
+    // 这个函数的作用是找到每一段变速中间速度变号的时间，积分在这些时间取到极值
+    // 变速事件本身的时间不算在里面，最后一个事件之后速度不变，没有变号
+    pub fn speed_sign_change_times(&self) -> Vec<f64> {
+        let list: &Vec<SpeedDistance> = &self.speed_distance;
+        let mut times: Vec<f64> = Vec::new();
+        for pair in list.windows(2) {
+            let (t1, v1, t2, v2, easing) = (pair[0].time, pair[0].speed as f64, pair[1].time, pair[1].speed as f64, pair[1].easing);
+            let length: f64 = t2 - t1;
+            if length <= 0.0 || easing == Easing::Step {
+                continue;
+            }
+            let roots: Vec<f64> = find_roots(|x| v1 + (v2 - v1) * easing.ease(x / length), length);
+            times.extend(roots.into_iter().filter(|&x| x > 0.0).map(|x| t1 + x));
+        }
+        times
+    }
+
+    // 从第start个速度事件开始往speed_distance里加点，start之前的点必须已经算好
+    fn push_speed_distance(&mut self, speed: &[Speed], start: usize) {
+        let mut distance_start: f64 = self.speed_distance.last().map_or(0.0, |speed_distance| speed_distance.distance);
+
+        let cal  = |speed_start: f32, speed_end: f32, time_start: f64, time_end: f64, easing: Easing| {
+            easing.segment_distance(speed_start as f64, speed_end as f64, time_end - time_start, time_end - time_start)
+        };
+
+        if !speed.is_empty() {
+            for i in start..speed.len() {
+                let time_end: f64 = speed[i].time;
+                let speed_end: f32 = speed[i].speed;
+                let mut easing: Easing = speed[i].easing;
+                let (time_start, speed_start): (f64, f32);
+
+                if i == 0 {
+                    time_start = 0.0;
+                    if time_end != 0.0 {
+                        if easing != Easing::Step {
+                            speed_start = speed_end;
+                            easing = Easing::Step;
+                        } else {
+                            speed_start = 1.0;
+                        }
+                        let speed_distance: SpeedDistance = speeddistance::SpeedDistance::new(0.0, speed_start, Easing::Step, 0.0);
+                        self.speed_distance.push(speed_distance);
+                    } else {
+                        speed_start = 0.0;
+                    }
+                } else {
+                    time_start = speed[i - 1].time;
+                    speed_start = speed[i - 1].speed;
+                }
+
+                distance_start += cal(speed_start, speed_end, time_start, time_end, easing);
+                self.speed_distance.push(speeddistance::SpeedDistance::new(time_end, speed_end, easing, distance_start));
+            }
+        } else {
+            self.speed_distance.push(speeddistance::SpeedDistance::new(0.0, 1.0, Easing::Step, 0.0));
+        }
+    }
+
+    // 从第start个note开始往trail_distance里加点，start为0时补上开头的点，最后补上结尾的点
+    // 加进去的点没有排序，forward为true时原有的点按时间排好序
+    fn push_trail_distance(&mut self, note: &[Note], start: usize, forward: bool) {
+        let keep: usize = self.trail_distance.len();
+        for n in note.iter().skip(start) {
+            //如果是Trail类型的note就处理trail_distance
+            if let Note::Trail(trail) = n {
+                let distance: f64 = self.find_distance_by_time(trail.time);
+                let trail_distance: TrailDistance = TrailDistance::new(trail.time, trail.degree, trail.delta, trail.prev_curv, trail.next_curv, distance);
+                self.trail_distance.push(trail_distance);
+                continue;
+            }
+
+            if let Note::Rotate(rotate) = n {
+                let distance: f64 = self.find_distance_by_time(rotate.time);
+                let trail_distance: TrailDistance = TrailDistance::new(rotate.time, rotate.degree, rotate.delta, rotate.prev_curv, rotate.next_curv, distance);
+                self.trail_distance.push(trail_distance);
+                continue;
+            }
+
+        }
+
+        if start == 0 {
+            if self.trail_distance.is_empty() {
+                self.trail_distance.push(TrailDistance::new(0.0, 90.0, 0.0, 0.0, 0.0, 0.0));
+            }
+
+            if self.trail_distance[0].time != 0.0 {
+                let start = self.trail_distance.first().unwrap();
+                let trail_distance: TrailDistance = TrailDistance::new(0.0, start.degree, 0.0, start.prev_curv, start.next_curv, 0.0);
+                self.trail_distance.insert(0, trail_distance);
+            }
+        }
+
+        let last: TrailDistance = latest(&self.trail_distance, keep, forward);
+        let distance: f64 = self.find_distance_by_time(last.time + 50000.0);
+        let trail_distance: TrailDistance = TrailDistance::new(last.time + 50000.0, last.degree + last.delta, 0.0, last.prev_curv, last.next_curv, distance);
+        self.trail_distance.push(trail_distance);
+    }
+
+    // 第i个note对应的手机角度点，附近100ms内有catch时跟着catch走
+    fn phone_trail_entries(&self, notes: &[Note], i: usize) -> Vec<TrailDistance> {
+        //计算手机怎么转 还有点瑕疵
+        let mut entries: Vec<TrailDistance> = Vec::new();
+        let mut flag: bool = false;
+        let note = &notes[i];
+        let time = note.get_time();
+        for j in 0..3{
+            if i + j >= notes.len(){
+                break;
+            }
+            let temp = &notes[j + i];
+            if temp.get_time() - time > 100.0{
+                break;
+            }
+            if let Note::Catch(catch) = temp{
+                let distance: f64 = self.find_distance_by_time(catch.time);
+                let degree = self.find_degree_by_time(catch.time);
+                let trail_distance: TrailDistance = TrailDistance::new(catch.time, degree, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+                flag = true;
+                break;
+            }
+        }
+        if flag{
+            return entries;
+        }
+        match note{
+            Note::Catch(catch) => {
+                let distance: f64 = self.find_distance_by_time(catch.time);
+                let degree = self.find_degree_by_time(catch.time);
+                let trail_distance: TrailDistance = TrailDistance::new(catch.time, degree, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+            }
+            Note::Tap(tap) => {
+                let distance: f64 = self.find_distance_by_time(tap.time);
+                let trail_distance: TrailDistance = TrailDistance::new(tap.time, tap.degree % 180.0, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+            }
+            Note::Flick(flick) => {
+                let distance: f64 = self.find_distance_by_time(flick.time);
+                let trail_distance: TrailDistance = TrailDistance::new(flick.time, flick.degree % 180.0, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+            }
+            Note::Slide(slide) => {
+                let distance: f64 = self.find_distance_by_time(slide.time);
+                let trail_distance: TrailDistance = TrailDistance::new(slide.time, slide.degree % 180.0, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+            }
+            Note::Bomb(bomb) => {
+                let distance: f64 = self.find_distance_by_time(bomb.time);
+                let degree = self.find_degree_by_time(bomb.time);
+                let trail_distance: TrailDistance = TrailDistance::new(bomb.time, degree % 180.0, 0.0, 0.0, 0.0, distance);
+                entries.push(trail_distance);
+            }
+            Note::Rotate(rotate) => {
+                let distance1: f64 = self.find_distance_by_time(rotate.time - 30.0);
+                let distancemid: f64 = self.find_distance_by_time(rotate.time);
+                let distance2: f64 = self.find_distance_by_time(rotate.time + 30.0);   
+                let trail_distance1: TrailDistance = TrailDistance::new(rotate.time - 30.0, rotate.degree % 180.0, 0.0, rotate.prev_curv, rotate.next_curv, distance1);
+                entries.push(trail_distance1);
+                let trail_distance_mid: TrailDistance = TrailDistance::new(rotate.time, rotate.degree % 180.0 + rotate.delta / 2.0, 0.0, rotate.prev_curv, rotate.next_curv, distancemid);
+                entries.push(trail_distance_mid);
+                let traildistance2: TrailDistance = TrailDistance::new(rotate.time + 30.0, rotate.degree % 180.0 + rotate.delta, 0.0, rotate.prev_curv, rotate.next_curv, distance2);
+                entries.push(traildistance2);
+            }
+            _ => {}
+        }
+        entries
+    }
+
+    // 从第start个note开始往phone_trail_distance里加点，start为0时补上开头的点，最后补上结尾的点
+    // 加进去的点没有排序，forward为true时原有的点按时间排好序
+    fn push_phone_trail_distance(&mut self, note: &[Note], start: usize, forward: bool) {
+        let keep: usize = self.phone_trail_distance.len();
+        for i in start..note.len(){
+            let entries: Vec<TrailDistance> = self.phone_trail_entries(note, i);
+            self.phone_trail_distance.extend(entries);
+        }
+        if start == 0 {
+            let trail_distance: TrailDistance = TrailDistance::new(0.0, 90.0, 0.0, 0.0,0.0, 0.0);
+            self.phone_trail_distance.insert(0, trail_distance);
+        }
+
+        let last: TrailDistance = latest(&self.phone_trail_distance, keep, forward);
+        let distance: f64 = self.find_distance_by_time(last.time + 200.0);
+        let trail_distance: TrailDistance = TrailDistance::new(last.time + 200.0, 90.0, 0.0, last.prev_curv, last.next_curv, distance);
+        self.phone_trail_distance.push(trail_distance);
+    }
+
+    pub fn get_y_from_x(&self, start: f32, end: f32, control_a: f32, control_b: f32, x: f32) -> f32 {
+        bezier::get_y_from_x(start, end, control_a, control_b, x)
+    }
+
+    // trail的曲线，角度的规则见trailcurve.rs
+    pub fn trail_curve(&self) -> TrailCurve<'_> {
+        TrailCurve::with_curves(&self.trail_distance, &self.trail_curves)
+    }
+
+    // 手机角度的曲线
+    pub fn phone_curve(&self) -> TrailCurve<'_> {
+        TrailCurve::with_curves(&self.phone_trail_distance, &self.phone_curves)
+    }
+
+    // 这个函数的作用是求两个trail之间进度为progress时的角度，结果不取模
+    pub fn find_degree_by_2_trails(&self, trail1: TrailDistance, trail2: TrailDistance, progress: f32) -> f32 {
+        TrailSegment::new(trail1, trail2).degree_at(progress)
+    }
+
+    pub fn find_degree_by_time(&self, time: f64) -> f32 {
+        self.trail_curve().degree_at(time, self.find_distance_by_time(time))
+    }
+
+    pub fn find_phone_degree_by_time(&self, time: f64) -> f32 {
+        self.phone_curve().degree_at(time, self.find_distance_by_time(time))
+    }
+
+    pub fn find_degree_by_distance(&self, distance: f64) -> f32 {
+        let time: f64 = self.find_time_by_distance(distance);
+        self.find_degree_by_time(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::note::{Tap, Flick, Slide, SlideType, Rotate, Catch, Trail, Bomb};
+
+    // 600个note，几种note轮流出现，每隔几个note有两个note在同一时间
+    fn sample() -> ChartData {
+        let mut data: ChartData = ChartData::new();
+        data.speed.push(Speed::new(0.0, 1.0, Easing::Step));
+        for i in 0..600 {
+            let time: f64 = 100.0 + (i / 7 * 6 + i % 7) as f64 * 41.0;
+            let degree: f32 = (i * 37 % 360) as f32;
+            data.note.push(match i % 8 {
+                0 => Note::Tap(Tap::new(time, degree)),
+                1 => Note::Catch(Catch::new(time, degree)),
+                2 => Note::Rotate(Rotate::new(time, degree, if i % 16 == 2 { 90.0 } else { -45.0 }, 20.0, 80.0)),
+                3 | 6 => Note::Trail(Trail::new(time, (i * 13 % 180) as f32, 0.0, 30.0, 70.0)),
+                4 => Note::Flick(Flick::new(time, degree)),
+                5 => Note::Slide(Slide::new(time, degree, SlideType::Small, degree + 90.0, 4, 2, 10.0, 90.0)),
+                _ => Note::Bomb(Bomb::new(time, degree)),
+            });
+            if i % 40 == 20 {
+                let easing: Easing = [Easing::Linear, Easing::Step, Easing::QuadInOut, Easing::SineOut][i / 40 % 4];
+                data.speed.push(Speed::new(time, 0.5 + (i % 9) as f32 * 0.25, easing));
+            }
+        }
+        data.sort();
+        data
+    }
+
+    fn assert_same_speed(a: &[SpeedDistance], b: &[SpeedDistance]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!(a.time == b.time && a.speed == b.speed && a.easing == b.easing && (a.distance - b.distance).abs() < 1e-6);
+        }
+    }
+
+    fn assert_same_trail(a: &[TrailDistance], b: &[TrailDistance]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!(a.time == b.time && a.degree == b.degree && a.delta == b.delta && (a.distance - b.distance).abs() < 1e-6);
+            assert!(a.prev_curv == b.prev_curv && a.next_curv == b.next_curv);
+        }
+    }
+
+    fn assert_same_timeline(a: &ChartTimeline, b: &ChartTimeline) {
+        assert_same_speed(a.speed_distance(), b.speed_distance());
+        assert_same_trail(a.trail_distance(), b.trail_distance());
+        assert_same_trail(a.phone_trail_distance(), b.phone_trail_distance());
+        assert_same_trail(a.trail_by_time(), b.trail_by_time());
+        assert_same_trail(a.trail_distance_plain(), b.trail_distance_plain());
+        assert_eq!(a.note_end_time(), b.note_end_time());
+        assert_eq!(a.note_end_max, b.note_end_max);
+        // 曲线没有PartialEq，按时间采样比较角度
+        assert_eq!(a.trail_curves.len(), b.trail_curves.len());
+        assert_eq!(a.phone_curves.len(), b.phone_curves.len());
+        let end: f64 = a.trail_by_time().last().unwrap().time;
+        for i in 0..=2000 {
+            let time: f64 = -100.0 + i as f64 * (end + 200.0) / 2000.0;
+            assert_eq!(a.find_degree_by_time(time), b.find_degree_by_time(time), "{}", time);
+            assert_eq!(a.find_phone_degree_by_time(time), b.find_phone_degree_by_time(time), "{}", time);
+        }
+    }
+
+    // 用edit_from改一次，再和完整重建的结果比较
+    fn check_edit<F: Fn(&mut ChartData)>(from_time: f64, f: F) {
+        let mut chart: Chart = Chart::new(sample());
+        chart.edit_from(from_time, &f);
+        let mut data: ChartData = sample();
+        f(&mut data);
+        data.sort();
+        assert_same_timeline(chart.timeline(), &ChartTimeline::new(&data));
+    }
+
+    fn time_of(index: usize) -> f64 {
+        sample().note[index].get_time()
+    }
+
+    fn move_note(data: &mut ChartData, index: usize, delta: f64) {
+        let time: f64 = data.note[index].get_time();
+        data.note[index].set_time(time + delta);
+    }
+
+    #[test]
+    fn rebuild_matches_full_build_at_start() {
+        check_edit(time_of(0), |data| move_note(data, 0, 15.0));
+        check_edit(0.0, |data| data.speed[0].speed = 2.0);
+        check_edit(0.0, |data| data.note.insert(0, Note::Trail(Trail::new(50.0, 10.0, 0.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn rebuild_matches_full_build_in_middle() {
+        for index in [299, 300, 301, 302, 303, 306] {
+            check_edit(time_of(index), |data| move_note(data, index, 7.0));
+            check_edit(time_of(index), |data| { data.note.remove(index); });
+        }
+        let speed: usize = sample().speed.len() / 2;
+        let speed_time: f64 = sample().speed[speed].time;
+        check_edit(speed_time, |data| data.speed[speed].easing = Easing::CubicIn);
+        check_edit(speed_time, |data| data.speed[speed].speed = -1.0);
+    }
+
+    #[test]
+    fn rebuild_matches_full_build_at_end() {
+        let last: usize = sample().note.len() - 1;
+        check_edit(time_of(last), |data| move_note(data, last, 100.0));
+        check_edit(time_of(last) + 1.0, |data| data.note.push(Note::Rotate(Rotate::new(30000.0, 0.0, 180.0, 50.0, 50.0))));
+        check_edit(time_of(last) + 1.0, |data| data.speed.push(Speed::new(30000.0, 3.0, Easing::Linear)));
+    }
+
+    #[test]
+    fn rebuild_matches_full_build_at_equal_times() {
+        // sample里第7个note之后每隔7个note就有一个和前一个note同一时间
+        let index: usize = (300..400).find(|&i| time_of(i) == time_of(i - 1)).unwrap();
+        let time: f64 = time_of(index);
+        check_edit(time, |data| data.note.insert(index, Note::Catch(Catch::new(time, 45.0))));
+        check_edit(time, |data| data.note.insert(index, Note::Trail(Trail::new(time, 90.0, 0.0, 0.0, 0.0))));
+        check_edit(time, |data| move_note(data, index, 0.0));
+        check_edit(time, |data| data.speed.push(Speed::new(time, 4.0, Easing::Step)));
+    }
+
+    #[test]
+    fn rebuild_matches_full_build_with_reverse_speed() {
+        // 有倒流时保留的点不在开头，要整个重新排序
+        let reverse = |data: &mut ChartData| {
+            let time: f64 = data.note[200].get_time();
+            data.speed.push(Speed::new(time, -2.0, Easing::Step));
+            data.speed.push(Speed::new(time + 500.0, 1.0, Easing::Step));
+            data.sort();
+        };
+        for index in [100, 300, 500] {
+            let from_time: f64 = time_of(index);
+            let edit = |data: &mut ChartData| move_note(data, index + 2, 7.0);
+            let mut data: ChartData = sample();
+            reverse(&mut data);
+            let mut chart: Chart = Chart::new(data.clone());
+            chart.edit_from(from_time, edit);
+            edit(&mut data);
+            data.sort();
+            assert_same_timeline(chart.timeline(), &ChartTimeline::new(&data));
+        }
+        // 贝塞尔缓动的速度可能在中间变成负数
+        let mut data: ChartData = sample();
+        data.speed.push(Speed::new(time_of(250), 1.0, Easing::Bezier { x1: 0.3, y1: -2.0, x2: 0.7, y2: 0.5 }));
+        data.sort();
+        let mut chart: Chart = Chart::new(data.clone());
+        chart.edit_from(time_of(400), |data| move_note(data, 420, 5.0));
+        move_note(&mut data, 420, 5.0);
+        data.sort();
+        assert_same_timeline(chart.timeline(), &ChartTimeline::new(&data));
+    }
+
+    // 改动前的数据只在debug版本里检查，见Chart::edit_from
+    #[cfg(debug_assertions)]
+    #[test]
+    fn edits_before_from_time_fall_back_to_full_build() {
+        // 调用方给的from_time比实际改动的位置晚
+        check_edit(time_of(400), |data| move_note(data, 100, 20.0));
+        check_edit(time_of(400), |data| data.speed[1].speed = 5.0);
+        check_edit(time_of(400), |data| move_note(data, 450, -time_of(450)));
+    }
+
+    #[test]
+    fn repeated_updates_do_not_duplicate() {
+        let fresh: Chart = Chart::new(sample());
+        let mut chart: Chart = Chart::new(sample());
+        chart.edit(|_| {});
+        chart.edit(|_| {});
+        chart.edit_from(time_of(300), |_| {});
+        chart.edit_from(time_of(300), |_| {});
+        assert_same_timeline(chart.timeline(), fresh.timeline());
+        assert_eq!(chart.hitsound_list.len(), fresh.hitsound_list.len());
+        assert_eq!(chart.data().note.len(), fresh.data().note.len());
+    }
+
+    #[test]
+    fn phone_trail_is_sorted_by_distance() {
+        let mut data: ChartData = sample();
+        // 倒流的一段，距离比前面的点还小
+        let time: f64 = data.note[200].get_time();
+        data.speed.push(Speed::new(time, -2.0, Easing::Step));
+        data.speed.push(Speed::new(time + 500.0, 1.0, Easing::Step));
+        data.sort();
+        let timeline: ChartTimeline = ChartTimeline::new(&data);
+        for trails in [timeline.trail_distance(), timeline.phone_trail_distance()] {
+            assert!(trails.windows(2).all(|pair| pair[0].distance < pair[1].distance || (pair[0].distance == pair[1].distance && pair[0].time <= pair[1].time)));
+        }
+    }
+
+    #[test]
+    fn trail_segments_match_linear_scan() {
+        let timeline: ChartTimeline = ChartTimeline::new(&sample());
+        let trails: &[TrailDistance] = timeline.trail_by_time();
+        assert!(trails.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        let last: f64 = trails.last().unwrap().time;
+        for i in 0..200 {
+            let start: f64 = -1000.0 + i as f64 * (last + 2000.0) / 200.0;
+            for length in [0.0, 10.0, 500.0, 5000.0] {
+                let end: f64 = start + length;
+                let expected: Vec<usize> = (0..trails.len() - 1)
+                    .filter(|&j| trails[j].time <= end && trails[j + 1].time >= start)
+                    .collect();
+                assert_eq!(timeline.trail_segments_in_time_range(start, end).collect::<Vec<usize>>(), expected, "{} {}", start, end);
+            }
+        }
+    }
+
+    // 3000个note的谱面上模拟拖动一个note，比较每次完整重建和增量重建的时间，运行方法：
+    //     cargo test --release rebuild_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn rebuild_benchmark() {
+        use std::time::{Duration, Instant};
+
+        let mut data: ChartData = ChartData::new();
+        data.speed.push(Speed::new(0.0, 1.0, Easing::Step));
+        for i in 0..3000 {
+            let time: f64 = 100.0 + i as f64 * 60.0;
+            let degree: f32 = (i * 37 % 360) as f32;
+            data.note.push(match i % 4 {
+                0 => Note::Tap(Tap::new(time, degree)),
+                1 => Note::Trail(Trail::new(time, (i * 13 % 180) as f32, 0.0, (i * 7 % 101) as f32, 50.0)),
+                2 => Note::Catch(Catch::new(time, 0.0)),
+                _ => Note::Flick(Flick::new(time, degree)),
+            });
+            if i % 50 == 25 {
+                data.speed.push(Speed::new(time, 0.5 + (i % 7) as f32 * 0.25, Easing::Linear));
+            }
+        }
+        let drags: usize = 200;
+        let index: usize = 2400; // 拖动靠后的一个tap
+        let from_time: f64 = 100.0 + index as f64 * 60.0 - 60.0;
+
+        let mut chart: Chart = Chart::new(data.clone());
+        let start: Instant = Instant::now();
+        for i in 0..drags {
+            chart.edit(|data| data.note[index].set_time(from_time + 60.0 + (i % 20) as f64));
+        }
+        let full: Duration = start.elapsed();
+
+        let mut chart: Chart = Chart::new(data);
+        let start: Instant = Instant::now();
+        for i in 0..drags {
+            chart.edit_from(from_time, |data| data.note[index].set_time(from_time + 60.0 + (i % 20) as f64));
+        }
+        let incremental: Duration = start.elapsed();
+
+        println!("{:>12}: {:>8.1} us/drag", "edit", full.as_secs_f64() * 1e6 / drags as f64);
+        println!("{:>12}: {:>8.1} us/drag", "edit_from", incremental.as_secs_f64() * 1e6 / drags as f64);
+        assert!(incremental < full);
+    }
+}
@@ -0,0 +1,26 @@
+// timesignature.rs
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+// 拍号事件，从time开始每小节numerator个1/denominator音符
+// 拍数按四分音符计，所以6/8拍一小节是3拍
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct TimeSignature {
+    pub time: f64,
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl TimeSignature {
+    pub fn new(time: f64, numerator: i32, denominator: i32) -> Self {
+        Self { time, numerator, denominator }
+    }
+
+    // 一小节有多少拍，拍号不合法时返回None
+    pub fn beats_per_measure(&self) -> Option<f64> {
+        if self.numerator <= 0 || self.denominator <= 0 {
+            return None;
+        }
+        Some(self.numerator as f64 * 4.0 / self.denominator as f64)
+    }
+}
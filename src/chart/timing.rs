@@ -0,0 +1,271 @@
+// timing.rs
+// bpm时间表：预先算好每个bpm事件开始时的累计拍数，时间和拍数互相换算时二分查找
+//
+// 约定：
+//     谱面时间0对应第0拍
+//     第一个bpm事件之前沿用第一个bpm
+//     没有bpm事件时按DEFAULT_BPM计算
+//     bpm为0的段里时间流逝但拍数不变，拍数换算回时间时取这一段的开始时间
+//     bpm为负的段里拍数倒退，同一个拍数对应多个时间时，拍数换算回时间取最早的那个
+use super::bpm::BPM;
+use super::timesignature::TimeSignature;
+
+pub const DEFAULT_BPM: f64 = 120.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BpmSegment {
+    pub time: f64,
+    pub bpm: f64,
+    pub beat: f64, // 这一段开始时的累计拍数
+}
+
+impl BpmSegment {
+    pub fn time_to_beat(&self, chart_time: f64) -> f64 {
+        self.beat + (chart_time - self.time) / 60000.0 * self.bpm
+    }
+
+    pub fn beat_to_time(&self, beat: f64) -> f64 {
+        if self.bpm == 0.0 {
+            return self.time;
+        }
+        self.time + (beat - self.beat) / self.bpm * 60000.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BpmTiming {
+    segments: Vec<BpmSegment>,
+    monotonic: bool, // 没有负的bpm时拍数不减，可以二分查找
+}
+
+impl Default for BpmTiming {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl BpmTiming {
+    // bpm需要已经按时间排好序
+    pub fn new(bpm: &[BPM]) -> Self {
+        if bpm.is_empty() {
+            return Self { segments: vec![BpmSegment { time: 0.0, bpm: DEFAULT_BPM, beat: 0.0 }], monotonic: true };
+        }
+
+        let mut segments: Vec<BpmSegment> = Vec::with_capacity(bpm.len());
+        // 第一段往前延伸到时间0
+        let mut beat: f64 = bpm[0].time / 60000.0 * bpm[0].bpm;
+        for i in 0..bpm.len() {
+            if i > 0 {
+                beat += (bpm[i].time - bpm[i - 1].time) / 60000.0 * bpm[i - 1].bpm;
+            }
+            segments.push(BpmSegment { time: bpm[i].time, bpm: bpm[i].bpm, beat });
+        }
+        let monotonic: bool = bpm.iter().all(|bpm| bpm.bpm >= 0.0);
+        Self { segments, monotonic }
+    }
+
+    pub fn segments(&self) -> &[BpmSegment] {
+        &self.segments
+    }
+
+    // 包含chart_time的那一段，第一个事件之前算第一段
+    pub fn segment_at_time(&self, chart_time: f64) -> &BpmSegment {
+        let index: usize = self.segments.partition_point(|segment| segment.time <= chart_time);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    // 最早到达beat的那一段，第一个事件之前算第一段
+    pub fn segment_at_beat(&self, beat: f64) -> &BpmSegment {
+        if self.monotonic {
+            // 拍数不减时，最早到达beat的是开始拍数小于beat的最后一段
+            let index: usize = self.segments.partition_point(|segment| segment.beat < beat);
+            return &self.segments[index.saturating_sub(1)];
+        }
+        let last: usize = self.segments.len() - 1;
+        // 第一段往前、最后一段往后延伸，拍数按bpm的正负趋于无穷
+        let unbounded = |segment: &BpmSegment, sign: f64| if segment.bpm == 0.0 { segment.beat } else { sign * segment.bpm.signum() * f64::INFINITY };
+        for (i, segment) in self.segments.iter().enumerate() {
+            let start: f64 = if i == 0 { unbounded(segment, -1.0) } else { segment.beat };
+            let end: f64 = if i == last { unbounded(segment, 1.0) } else { self.segments[i + 1].beat };
+            if start.min(end) <= beat && beat <= start.max(end) {
+                return segment;
+            }
+        }
+        &self.segments[0]
+    }
+
+    pub fn time_to_beat(&self, chart_time: f64) -> f64 {
+        self.segment_at_time(chart_time).time_to_beat(chart_time)
+    }
+
+    pub fn beat_to_time(&self, beat: f64) -> f64 {
+        self.segment_at_beat(beat).beat_to_time(beat)
+    }
+
+    pub fn bpm_at(&self, chart_time: f64) -> f64 {
+        self.segment_at_time(chart_time).bpm
+    }
+}
+
+// 小节表：每个拍号事件开始时的拍数和小节数
+//
+// 约定：
+//     第0拍是第0小节的开始
+//     第一个拍号事件之前按4/4拍
+//     拍号事件总是开始新的一小节，被打断的小节也算一整个小节
+//     小节数落在被打断的小节缺掉的部分时，按前一段的拍号往后算
+#[derive(Clone, Copy, Debug)]
+pub struct MeterSegment {
+    pub beat: f64,
+    pub measure: f64,
+    pub beats_per_measure: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MeterTiming {
+    segments: Vec<MeterSegment>,
+}
+
+impl Default for MeterTiming {
+    fn default() -> Self {
+        Self { segments: vec![MeterSegment { beat: 0.0, measure: 0.0, beats_per_measure: 4.0 }] }
+    }
+}
+
+impl MeterTiming {
+    // time_signature需要已经按时间排好序，不合法的拍号会被忽略
+    pub fn new(time_signature: &[TimeSignature], bpm_timing: &BpmTiming) -> Self {
+        let mut timing: MeterTiming = MeterTiming::default();
+        for signature in time_signature {
+            let beats_per_measure: f64 = match signature.beats_per_measure() {
+                Some(beats) => beats,
+                None => continue,
+            };
+            let beat: f64 = bpm_timing.time_to_beat(signature.time);
+            let last: MeterSegment = *timing.segments.last().unwrap();
+            if beat <= last.beat {
+                // 和上一个拍号在同一拍（或者在第0拍之前），直接替换
+                let index: usize = timing.segments.len() - 1;
+                timing.segments[index].beats_per_measure = beats_per_measure;
+                continue;
+            }
+            // 浮点误差不应该多算一个小节
+            let measures: f64 = ((beat - last.beat) / last.beats_per_measure - 1e-9).ceil();
+            timing.segments.push(MeterSegment { beat, measure: last.measure + measures, beats_per_measure });
+        }
+        timing
+    }
+
+    pub fn segments(&self) -> &[MeterSegment] {
+        &self.segments
+    }
+
+    pub fn segment_at_beat(&self, beat: f64) -> &MeterSegment {
+        let index: usize = self.segments.partition_point(|segment| segment.beat <= beat);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    pub fn segment_at_measure(&self, measure: f64) -> &MeterSegment {
+        let index: usize = self.segments.partition_point(|segment| segment.measure <= measure);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    pub fn beat_to_measure(&self, beat: f64) -> f64 {
+        let segment: &MeterSegment = self.segment_at_beat(beat);
+        segment.measure + (beat - segment.beat) / segment.beats_per_measure
+    }
+
+    pub fn measure_to_beat(&self, measure: f64) -> f64 {
+        let segment: &MeterSegment = self.segment_at_measure(measure);
+        segment.beat + (measure - segment.measure) * segment.beats_per_measure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+
+    fn timing(bpm: &[(f64, f64)]) -> BpmTiming {
+        let bpm: Vec<BPM> = bpm.iter().map(|&(time, bpm)| BPM::new(time, bpm)).collect();
+        BpmTiming::new(&bpm)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    // -2000ms到9000ms每37ms取一个点，拍数-8到40每0.125拍取一个点
+    fn times() -> impl Iterator<Item = f64> {
+        (0..300).map(|i| -2000.0 + i as f64 * 37.0)
+    }
+
+    fn beats() -> impl Iterator<Item = f64> {
+        (0..=384).map(|i| -8.0 + i as f64 * 0.125)
+    }
+
+    #[test]
+    fn positive_bpm_inverts() {
+        // 第一个事件不在0，之前沿用第一个bpm
+        let timing: BpmTiming = timing(&[(250.0, 150.0), (1000.0, 90.0), (1000.0, 200.0), (4321.5, 175.5)]);
+        assert_close(timing.time_to_beat(0.0), 0.0);
+        for time in times() {
+            assert_close(timing.beat_to_time(timing.time_to_beat(time)), time);
+        }
+        for beat in beats() {
+            assert_close(timing.time_to_beat(timing.beat_to_time(beat)), beat);
+        }
+    }
+
+    #[test]
+    fn zero_bpm_maps_to_segment_start() {
+        // 第2拍停住1000ms
+        let timing: BpmTiming = timing(&[(0.0, 120.0), (1000.0, 0.0), (2000.0, 60.0)]);
+        assert_close(timing.time_to_beat(1500.0), 2.0);
+        assert_close(timing.beat_to_time(2.0), 1000.0);
+        assert_close(timing.beat_to_time(2.5), 2500.0);
+        for time in times().filter(|&time| !(time > 1000.0 && time <= 2000.0)) {
+            assert_close(timing.beat_to_time(timing.time_to_beat(time)), time);
+        }
+        for beat in beats() {
+            assert_close(timing.time_to_beat(timing.beat_to_time(beat)), beat);
+        }
+    }
+
+    #[test]
+    fn negative_bpm_maps_to_earliest_time() {
+        // 0到1000ms走到第2拍，1000到3000ms倒回第0拍，之后再往前走
+        let timing: BpmTiming = timing(&[(0.0, 120.0), (1000.0, -60.0), (3000.0, 120.0)]);
+        assert_close(timing.time_to_beat(2000.0), 1.0);
+        assert_close(timing.time_to_beat(3000.0), 0.0);
+        assert_close(timing.beat_to_time(1.0), 500.0);
+        assert_close(timing.beat_to_time(-1.0), -500.0);
+        assert_close(timing.beat_to_time(3.0), 4500.0);
+        for time in times().filter(|&time| time <= 1000.0) {
+            assert_close(timing.beat_to_time(timing.time_to_beat(time)), time);
+        }
+        for beat in beats() {
+            assert_close(timing.time_to_beat(timing.beat_to_time(beat)), beat);
+        }
+    }
+
+    #[test]
+    fn empty_bpm_uses_default() {
+        let timing: BpmTiming = timing(&[]);
+        assert_close(timing.time_to_beat(60000.0), DEFAULT_BPM);
+        assert_close(timing.beat_to_time(DEFAULT_BPM), 60000.0);
+    }
+
+    #[test]
+    fn chart_conversions_use_timing() {
+        let mut data: ChartData = ChartData::new();
+        data.bpm = vec![BPM::new(0.0, 120.0), BPM::new(1000.0, 0.0), BPM::new(2000.0, 240.0)];
+        let chart: Chart = Chart::new(data);
+        for beat in beats() {
+            assert_close(chart.chart_time_to_beat(chart.beat_to_chart_time(beat)), beat);
+        }
+        assert_close(chart.beat_to_chart_time(2.0), 1000.0);
+        assert_close(chart.chart_time_to_beat(2250.0), 3.0);
+    }
+}
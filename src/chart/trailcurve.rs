@@ -0,0 +1,388 @@
+// trailcurve.rs
+// trail的曲线：一串按距离排好序的TrailDistance（trail_distance或者phone_trail_distance），相邻两个点之间用贝塞尔曲线插值
+//
+// 角度的约定：
+//     每个点从degree转到degree + delta（rotate的转动量），下一段从degree + delta开始
+//     trail是过圆心的直线，degree和degree + 180是同一条线
+//     插值前先把两个角度都换到[0, 360)，终点再换成和起点相差不到90的那个等价角度（加减180的倍数）
+//     正好相差90的时候两边一样近：起点在[0, 180)时终点不动，起点在[180, 360)时换到另一边，和以前的结果保持一致
+//     TrailSegment的结果是连续的角度，不取模，画线时相邻的采样不会跳变
+//     TrailCurve::degree_at的结果取模到[0, 180)
+use std::collections::HashMap;
+
+use super::search::{SearchMode, find_pos_by_distance};
+use super::traildistance::TrailDistance;
+use super::bezier::BezierCurve;
+
+// 相邻两个点之间的一段，角度在new的时候就换算好，采样多次时不用重复算
+#[derive(Clone, Copy)]
+pub struct TrailSegment {
+    pub from: TrailDistance,
+    pub to: TrailDistance,
+    degree_from: f32,
+    degree_to: f32,
+    curve: BezierCurve,
+}
+
+impl TrailSegment {
+    pub fn new(from: TrailDistance, to: TrailDistance) -> Self {
+        Self::with_curve(from, to, Self::curve_of(&from, &to))
+    }
+
+    // curve需要是curve_of(from, to)得到的曲线（可以带查找表）
+    pub fn with_curve(from: TrailDistance, to: TrailDistance, curve: BezierCurve) -> Self {
+        let (degree_from, degree_to): (f32, f32) = unwrap_degrees(from.degree + from.delta, to.degree);
+        Self { from, to, degree_from, degree_to, curve }
+    }
+
+    // 两个点之间的曲线，起点用next_curv，终点用prev_curv
+    pub fn curve_of(from: &TrailDistance, to: &TrailDistance) -> BezierCurve {
+        BezierCurve::new(from.next_curv / 100.0, to.prev_curv / 100.0)
+    }
+
+    // progress是这一段里的进度，0是from，1是to，结果不取模
+    pub fn degree_at(&self, progress: f32) -> f32 {
+        self.curve.y_at(progress) * (self.degree_to - self.degree_from) + self.degree_from
+    }
+
+    // 按距离算的进度，倒流时可能不在0到1之间
+    pub fn progress_at_distance(&self, distance: f64) -> f32 {
+        ((distance - self.from.distance) / (self.to.distance - self.from.distance)) as f32
+    }
+}
+
+// 曲线只和两头的曲率有关，key是(起点的next_curv, 终点的prev_curv)
+pub type CurveCache = HashMap<(u32, u32), BezierCurve>;
+
+#[derive(Clone, Copy)]
+pub struct TrailCurve<'a> {
+    trails: &'a [TrailDistance],
+    curves: &'a [BezierCurve], // 每一段预先建好的曲线，第i个是第i个点到下一个点，可以是空的
+}
+
+impl<'a> TrailCurve<'a> {
+    // trails需要按距离排好序，距离相同的按时间排序
+    pub fn new(trails: &'a [TrailDistance]) -> Self {
+        Self { trails, curves: &[] }
+    }
+
+    // curves是build_curves(trails)的结果
+    pub fn with_curves(trails: &'a [TrailDistance], curves: &'a [BezierCurve]) -> Self {
+        Self { trails, curves }
+    }
+
+    // 这个函数的作用是给每一段建好带查找表的曲线，曲率一样的段共用一张表
+    pub fn build_curves(trails: &[TrailDistance]) -> Vec<BezierCurve> {
+        Self::build_curves_cached(trails, &mut CurveCache::new())
+    }
+
+    // 和build_curves一样，cache里已有的曲线直接用，新建的曲线也放进cache，重建时可以不用重新建表
+    pub fn build_curves_cached(trails: &[TrailDistance], cache: &mut CurveCache) -> Vec<BezierCurve> {
+        trails.windows(2)
+            .map(|pair| {
+                let key: (u32, u32) = (pair[0].next_curv.to_bits(), pair[1].prev_curv.to_bits());
+                *cache.entry(key).or_insert_with(|| TrailSegment::curve_of(&pair[0], &pair[1]).with_table())
+            })
+            .collect()
+    }
+
+    pub fn trails(&self) -> &'a [TrailDistance] {
+        self.trails
+    }
+
+    // 第index个点和下一个点之间的一段，最后一个点没有下一段
+    pub fn segment(&self, index: usize) -> Option<TrailSegment> {
+        match (self.trails.get(index), self.trails.get(index + 1)) {
+            (Some(from), Some(to)) => match self.curves.get(index) {
+                Some(curve) if self.curves.len() + 1 == self.trails.len() => Some(TrailSegment::with_curve(*from, *to, *curve)),
+                _ => Some(TrailSegment::new(*from, *to)),
+            },
+            _ => None,
+        }
+    }
+
+    // 距离所在那一段的起点下标：距离小于等于distance的最后一个点，在第一个点之前时是0
+    pub fn index_at_distance(&self, distance: f64) -> usize {
+        find_pos_by_distance(self.trails, distance, SearchMode::LastAtOrBefore).unwrap_or(0)
+    }
+
+    // 这个函数的作用是求time时的角度，distance是time对应的距离，结果在[0, 180)
+    // 按distance找到所在的一段，按距离算进度
+    // time不晚于这一段起点的时间时（第一个点之前，或者倒流回到起点之前）直接取起点的degree
+    // 最后一个点之后保持degree + delta
+    pub fn degree_at(&self, time: f64, distance: f64) -> f32 {
+        let index: usize = self.index_at_distance(distance);
+        let from: TrailDistance = match self.trails.get(index) {
+            Some(from) => *from,
+            None => return 0.0,
+        };
+        if time <= from.time {
+            return from.degree;
+        }
+        let mut result: f32 = match self.segment(index) {
+            Some(segment) => segment.degree_at(segment.progress_at_distance(distance)),
+            None => from.degree + from.delta,
+        };
+        if result < 0.0 {
+            result += 360.0;
+        }
+        result % 180.0
+    }
+
+    // 这个函数的作用是求距离为distance时的角度，不知道时间时用，结果在[0, 180)
+    // 和degree_at一样，只是把“time不晚于起点”换成“distance不超过起点”，距离单调时两者相同
+    pub fn degree_at_distance(&self, distance: f64) -> f32 {
+        let index: usize = self.index_at_distance(distance);
+        match self.trails.get(index) {
+            // 时间取无穷大，degree_at就只按距离算
+            Some(from) if distance > from.distance => self.degree_at(f64::INFINITY, distance),
+            Some(from) => from.degree,
+            None => 0.0,
+        }
+    }
+}
+
+// 把起点和终点的角度换算成插值用的角度，规则见文件开头
+fn unwrap_degrees(degree1: f32, degree2: f32) -> (f32, f32) {
+    let mut degree1: f32 = degree1 % 360.0;
+    if degree1 < 0.0 {
+        degree1 += 360.0;
+    }
+    let mut degree2: f32 = degree2 % 360.0;
+    if degree2 < 0.0 {
+        degree2 += 360.0;
+    }
+
+    if degree1 >= 180.0 {
+        if (degree1 - (degree2 + 360.0)).abs() <= 90.0 {
+            degree2 += 360.0;
+        } else if (degree1 - (degree2 + 180.0)).abs() <= 90.0 {
+            degree2 += 180.0;
+        } else if (degree1 - (degree2 - 180.0)).abs() <= 90.0 {
+            degree2 -= 180.0;
+        }
+    } else {
+        if (degree1 - (degree2 - 360.0)).abs() < 90.0 {
+            degree2 -= 360.0;
+        } else if (degree1 - (degree2 - 180.0)).abs() < 90.0 {
+            degree2 -= 180.0;
+        } else if (degree1 - (degree2 + 180.0)).abs() < 90.0 {
+            degree2 += 180.0;
+        }
+    }
+    (degree1, degree2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::chart::Chart;
+    use crate::chart::chartdata::ChartData;
+    use crate::chart::easing::Easing;
+    use crate::chart::note::{Note, Tap, Rotate, Catch, Trail};
+    use crate::chart::speed::Speed;
+
+    // 以下是TrailCurve之前ChartTimeline里的实现，用来对照
+    fn old_get_y_from_x(start: f32, end: f32, control_a: f32, control_b: f32, x: f32) -> f32 {
+        let calculate_x = |a: f32, b: f32, time: f32| {
+            time * (3.0 * a - 6.0 * a * time + 3.0 * b * time + time * time + 3.0 * a * time * time - 3.0 * b * time * time)
+        };
+        let calculate_y = |time: f32| -2.0 * time * time * time + 3.0 * time * time;
+        let (mut upper_time, mut lower_time, mut time): (f32, f32, f32) = (1.0, 0.0, 0.0);
+        for _ in 0..20 {
+            time = (upper_time + lower_time) / 2.0;
+            if calculate_x(control_a, 1.0 - control_b, time) < x {
+                lower_time = time;
+            } else {
+                upper_time = time;
+            }
+        }
+        calculate_y(time) * (end - start) + start
+    }
+
+    fn old_unwrap(mut degree1: f32, mut degree2: f32) -> (f32, f32) {
+        degree1 %= 360.0;
+        if degree1 < 0.0 {
+            degree1 += 360.0;
+        }
+        degree2 %= 360.0;
+        if degree2 < 0.0 {
+            degree2 += 360.0;
+        }
+        if degree1 >= 180.0 {
+            if (degree1 - (degree2 + 360.0)).abs() <= 90.0 {
+                degree2 += 360.0;
+            } else if (degree1 - (degree2 + 180.0)).abs() <= 90.0 {
+                degree2 += 180.0;
+            } else if (degree1 - (degree2 - 180.0)).abs() <= 90.0 {
+                degree2 -= 180.0;
+            }
+        } else if (degree1 - (degree2 - 360.0)).abs() < 90.0 {
+            degree2 -= 360.0;
+        } else if (degree1 - (degree2 - 180.0)).abs() < 90.0 {
+            degree2 -= 180.0;
+        } else if (degree1 - (degree2 + 180.0)).abs() < 90.0 {
+            degree2 += 180.0;
+        }
+        (degree1, degree2)
+    }
+
+    // find_degree_by_time和find_phone_degree_by_time，两者只有用的数组不同
+    fn old_degree_by_time(trails: &[TrailDistance], time: f64, distance: f64) -> f32 {
+        let position1: usize = find_pos_by_distance(trails, distance, SearchMode::LastAtOrBefore).unwrap_or(0);
+        let position2: usize = position1 + 1;
+        let trail1: TrailDistance = trails[position1];
+        if time <= trail1.time {
+            return trail1.degree;
+        }
+        let mut result: f32 = if position2 < trails.len() {
+            let trail2: TrailDistance = trails[position2];
+            let (degree1, degree2): (f32, f32) = old_unwrap(trail1.degree + trail1.delta, trail2.degree);
+            let progress: f32 = ((distance - trail1.distance) / (trail2.distance - trail1.distance)) as f32;
+            old_get_y_from_x(degree1, degree2, trail1.next_curv / 100.0, trail2.prev_curv / 100.0, progress)
+        } else {
+            trail1.degree + trail1.delta
+        };
+        if result < 0.0 {
+            result += 360.0;
+        }
+        result % 180.0
+    }
+
+    fn old_degree_by_2_trails(trail1: TrailDistance, trail2: TrailDistance, progress: f32) -> f32 {
+        let calculate_delta = |degree1: f32, degree2: f32| {
+            let delta: f32 = (degree1 - degree2).abs() % 360.0;
+            if delta > 180.0 { 360.0 - delta } else { delta }
+        };
+        let degree1: f32 = trail1.degree + trail1.delta;
+        let mut degree2: f32 = trail2.degree;
+        if calculate_delta(degree2, degree1) < calculate_delta(degree2 + 180.0, degree1) {
+            degree2 = (degree2 + 180.0) % 360.0;
+        }
+        let (degree1, degree2): (f32, f32) = old_unwrap(degree1, degree2);
+        old_get_y_from_x(degree1, degree2, trail1.next_curv / 100.0, trail2.prev_curv / 100.0, progress)
+    }
+
+    // 角度按180取模比较，贝塞尔曲线的解法换过，允许很小的误差
+    fn assert_same_line(new: f32, old: f32, context: &str) {
+        let difference: f32 = (new - old).rem_euclid(180.0);
+        assert!(difference.min(180.0 - difference) < 0.02, "{}: new {} old {}", context, new, old);
+    }
+
+    // 各种角度差（包括正好90和180）、曲率、rotate转动量，速度有缓动和倒流，还有跟着catch走的手机角度
+    fn sample() -> Chart {
+        let mut data: ChartData = ChartData::new();
+        data.speed = vec![
+            Speed::new(0.0, 1.0, Easing::Step),
+            Speed::new(3000.0, 2.0, Easing::Linear),
+            Speed::new(6000.0, -1.0, Easing::Step),
+            Speed::new(6500.0, 1.5, Easing::SineInOut),
+        ];
+        let degrees: [f32; 12] = [0.0, 90.0, 0.0, 179.0, 1.0, 270.0, 45.0, 225.0, 359.0, 180.0, 91.0, 300.5];
+        let curvatures: [f32; 4] = [0.0, 30.0, 70.0, 100.0];
+        for i in 0..60 {
+            let time: f64 = 200.0 + i as f64 * 173.0;
+            let degree: f32 = degrees[i % degrees.len()] + (i / degrees.len()) as f32 * 17.0;
+            let (prev_curv, next_curv): (f32, f32) = (curvatures[i % 4], curvatures[(i / 4) % 4]);
+            data.note.push(match i % 5 {
+                2 => Note::Rotate(Rotate::new(time, degree, [90.0, -135.0, 360.0][i % 3], prev_curv, next_curv)),
+                4 => Note::Catch(Catch::new(time, degree)),
+                _ => Note::Trail(Trail::new(time, degree, 0.0, prev_curv, next_curv)),
+            });
+            if i % 7 == 0 {
+                data.note.push(Note::Tap(Tap::new(time + 50.0, degree)));
+            }
+        }
+        Chart::new(data)
+    }
+
+    fn sample_times() -> impl Iterator<Item = f64> {
+        (0..2600).map(|i| -100.0 + i as f64 * 4.7)
+    }
+
+    #[test]
+    fn trail_degree_matches_old_implementation() {
+        let chart: Chart = sample();
+        let timeline = chart.timeline();
+        for time in sample_times() {
+            let old: f32 = old_degree_by_time(timeline.trail_distance(), time, timeline.find_distance_by_time(time));
+            assert_same_line(timeline.find_degree_by_time(time), old, &format!("trail at {}", time));
+        }
+    }
+
+    #[test]
+    fn phone_degree_matches_old_implementation() {
+        let chart: Chart = sample();
+        let timeline = chart.timeline();
+        for time in sample_times() {
+            let old: f32 = old_degree_by_time(timeline.phone_trail_distance(), time, timeline.find_distance_by_time(time));
+            assert_same_line(timeline.find_phone_degree_by_time(time), old, &format!("phone at {}", time));
+        }
+    }
+
+    #[test]
+    fn two_trails_match_old_implementation() {
+        let chart: Chart = sample();
+        let trails: &[TrailDistance] = chart.timeline().trail_distance();
+        for pair in trails.windows(2) {
+            let segment: TrailSegment = TrailSegment::new(pair[0], pair[1]);
+            for i in 0..=50 {
+                let progress: f32 = i as f32 / 50.0;
+                let old: f32 = old_degree_by_2_trails(pair[0], pair[1], progress);
+                assert_same_line(segment.degree_at(progress), old, &format!("segment at {} progress {}", pair[0].time, progress));
+            }
+        }
+    }
+
+    // 模拟渲染一帧：画面里的每一段trail采样100次，每次算两个端点的角度（和trailrenderer一样）
+    // 比较以前的二分、不带查找表的牛顿法和带查找表的牛顿法，运行方法：
+    //     cargo test --release frame_time_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn frame_time_benchmark() {
+        use std::hint::black_box;
+        use std::time::{Duration, Instant};
+
+        let mut data: ChartData = ChartData::new();
+        for i in 0..3000 {
+            let curvature: f32 = (i * 37 % 101) as f32;
+            data.note.push(Note::Trail(Trail::new(i as f64 * 60.0, (i * 53 % 360) as f32, 0.0, curvature, 100.0 - curvature)));
+        }
+        let chart: Chart = Chart::new(data);
+        let trails: &[TrailDistance] = chart.timeline().trail_distance();
+        let curve: TrailCurve = chart.timeline().trail_curve();
+        let frames: usize = 2000;
+        let visible: f64 = 1500.0; // 画面里能看到的时间长度，毫秒
+        let samples: usize = 100;
+
+        // segment_at按下标建好一段，每帧每段建一次，再在这一段上采样
+        let run = |name: &str, segment_at: &dyn Fn(usize) -> Box<dyn Fn(f32) -> f32>| {
+            let start: Instant = Instant::now();
+            for frame in 0..frames {
+                let time: f64 = frame as f64 * 1000.0 / 60.0;
+                let first: usize = trails.partition_point(|trail| trail.time < time).saturating_sub(1);
+                for i in (first..trails.len() - 1).take_while(|&i| trails[i].time < time + visible) {
+                    let degree_at = segment_at(i);
+                    for j in 0..samples {
+                        black_box(degree_at(j as f32 / samples as f32));
+                        black_box(degree_at((j + 1) as f32 / samples as f32));
+                    }
+                }
+            }
+            let elapsed: Duration = start.elapsed();
+            println!("{:>16}: {:>8.1} us/frame", name, elapsed.as_secs_f64() * 1e6 / frames as f64);
+        };
+        run("old bisection", &|i| {
+            let (from, to): (TrailDistance, TrailDistance) = (trails[i], trails[i + 1]);
+            Box::new(move |progress| old_degree_by_2_trails(from, to, progress))
+        });
+        run("newton", &|i| {
+            let segment: TrailSegment = TrailSegment::new(trails[i], trails[i + 1]);
+            Box::new(move |progress| segment.degree_at(progress))
+        });
+        run("newton + table", &|i| {
+            let segment: TrailSegment = curve.segment(i).unwrap();
+            Box::new(move |progress| segment.degree_at(progress))
+        });
+    }
+}
@@ -1,17 +1,18 @@
-use serde::{Serialize, Serializer, Deserialize};
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct TrailDistance {
-    pub time: f32,
+    pub time: f64,
     pub degree: f32,
     pub delta: f32,
     pub prev_curv: f32,
     pub next_curv: f32,
-    pub distance: f32,
+    pub distance: f64,
 }
 
 impl TrailDistance {
-    pub fn new(time: f32, degree: f32, delta: f32, prev_curv: f32, next_curv: f32, distance: f32) -> Self {
+    pub fn new(time: f64, degree: f32, delta: f32, prev_curv: f32, next_curv: f32, distance: f64) -> Self {
         Self { time, degree, delta, prev_curv, next_curv, distance}
     }
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use macroquad::prelude::*;
 
 use crate::chart::chart::ChartProperties;
 use crate::chart::{chart::Chart, traildistance::TrailDistance};
-use crate::chart::note::{Note, Tap, Flick, Slide, Rotate, Catch, Trail, Bomb};
+use crate::chart::note::{Note, Tap, Flick, Slide, SlideType, Rotate, Catch, Trail, Bomb};
 use crate::chart::bpm::BPM;
 use crate::chart::speed::Speed;
 
@@ -81,24 +81,8 @@ pub fn draw_rotate_simple(x: f32, y: f32, radius: f32, degree: f32, delta: f32)
 
 pub fn draw_note(chart: Chart, chart_property: &ChartProperties, note_texture_manager: &NoteTextureManager, debug: bool) {
     // note部分
-    for i in 0..chart.note.len() {
-        // continue;
-        let note = &chart.note[i];
-        match note {
-            Note::Slide(slide) => {
-                let snaptime = 60.0 / chart.bpm[0].bpm * 1000.0;
-                let time2 = slide.time + slide.amount as f32 * snaptime / slide.snap as f32;
-                if time2 < chart_property.start_chart_time || slide.time > chart_property.end_chart_time{
-                    continue;
-                }
-            }
-            _ => {
-                if (note.get_time() < chart_property.start_chart_time || note.get_time() > chart_property.end_chart_time){
-                    continue;
-                }
-            }
-        }
-
+    // 只取显示时间范围内的note，slide按整个持续时间算
+    for note in chart.notes_in_time_range(chart_property.start_chart_time, chart_property.end_chart_time) {
         match note {
             Note::Tap(tap) => { 
                 let degree = tap.degree;
@@ -143,33 +127,29 @@ pub fn draw_note(chart: Chart, chart_property: &ChartProperties, note_texture_ma
                 let (x, y) = (600.0 + radius * truedegree.to_radians().cos(), 400.0 - radius * truedegree.to_radians().sin());
                 if slide.time > chart_property.start_chart_time && slide.time < chart_property.end_chart_time{
                     match slidetype{
-                        0 => {
+                        SlideType::TapHead => {
                             note_texture_manager.tap_texture.middle_draw(x, y, xscale / 750.0, yscale / 750.0, degree + 90.0);
                         },
-                        1 => {
+                        SlideType::FlickHead => {
                             note_texture_manager.flick_texture.middle_draw(x, y, xscale / 750.0, yscale / 750.0, degree + 90.0);
                         },
-                        2 => {
+                        SlideType::Small => {
                             note_texture_manager.small_slide_texture.middle_draw(x, y, xscale / 750.0, yscale / 750.0, degree + 90.0);
                         },
-                        3 => {
+                        SlideType::Big => {
                             note_texture_manager.big_slide_texture.middle_draw(x, y, xscale / 750.0, yscale / 750.0, degree + 90.0);
                         },
-                        _ => {}
                     }
                 }
 
-                for j in 1..slide.amount {
-                    // 填4就是4分音符
-                    let snaptime = 60.0 / chart.bpm[0].bpm * 1000.0;
-                    let time = slide.time + j as f32 * snaptime / slide.snap as f32;
+                let end_time = chart.timeline().note_end(note);
+                for time in slide.tick_times(&chart) {
                     let enddegree = slide.end_degree;
-                    let end_distance_1 = chart.find_distance_by_time(slide.time + slide.amount as f32 * snaptime / slide.snap as f32);
                     let this_distance_1 = chart.find_distance_by_time(time);
                     if this_distance_1 > chart_property.end_distance || this_distance_1 < chart_property.start_distance{
                         continue;
                     }
-                    let progress = (time - slide.time) / (slide.amount as f32 * snaptime / slide.snap as f32);
+                    let progress = ((time - slide.time) / (end_time - slide.time)) as f32;
                     let degree = chart.get_y_from_x(slide.degree, enddegree, slide.prev_curv / 100.0, slide.next_curv / 100.0, progress);
                     // let degree = slide.degree + (enddegree - slide.degree) * j as f32 / slide.amount as f32;
                     let radius = distance_to_radius(327.5, this_distance_1, chart_property.start_distance, chart_property.end_distance);
@@ -2,7 +2,7 @@ use std::f32::RADIX;
 
 use macroquad::{time, ui::{self, Skin}};
 use crate::chart::{chart, note::{Catch, Flick, Note, Rotate, Slide, Tap, Trail}};
-use crate::chart::{chart::Chart, traildistance::TrailDistance};
+use crate::chart::{chart::Chart, traildistance::TrailDistance, trailcurve::TrailSegment};
 use crate::chart::chart::ChartProperties;
 use macroquad::prelude::*;
 
@@ -15,8 +15,8 @@ pub fn dwaw_arc(x: f32, y: f32, radius: f32, start: f32, end: f32, thickness: f3
     }
 }
 
-pub fn distance_to_radius(max_radius: f32, distance: f32, start_distance: f32, end_distance: f32) -> f32 {
-    let x: f32 = (distance - start_distance) / (end_distance - start_distance); //还有%多少到判定区
+pub fn distance_to_radius(max_radius: f32, distance: f64, start_distance: f64, end_distance: f64) -> f32 {
+    let x: f32 = ((distance - start_distance) / (end_distance - start_distance)) as f32; //还有%多少到判定区
 
     let x1 = 1.0 - x;
 
@@ -40,6 +40,29 @@ pub fn distance_to_radius(max_radius: f32, distance: f32, start_distance: f32, e
     radius
 }
 
+// 把两个采样点(时间, 距离)之间的线段裁剪到显示范围内，两点之间按线性插值
+// 返回裁剪后的(时间1, 距离1, 时间2, 距离2)，完全不在范围内时返回None
+fn clip_sample(point1: (f64, f64), point2: (f64, f64), start_distance: f64, end_distance: f64) -> Option<(f64, f64, f64, f64)> {
+    let ((t1, d1), (t2, d2)) = (point1, point2);
+    if d1.max(d2) < start_distance || d1.min(d2) > end_distance {
+        return None;
+    }
+    if d1 == d2 {
+        return Some((t1, d1, t2, d2));
+    }
+    let s_start = (start_distance - d1) / (d2 - d1);
+    let s_end = (end_distance - d1) / (d2 - d1);
+    let s_min = s_start.min(s_end).max(0.0);
+    let s_max = s_start.max(s_end).min(1.0);
+    if s_min > s_max {
+        return None;
+    }
+    let lerp = |s: f64| (t1 + (t2 - t1) * s, d1 + (d2 - d1) * s);
+    let (time1, distance1) = lerp(s_min);
+    let (time2, distance2) = lerp(s_max);
+    Some((time1, distance1, time2, distance2))
+}
+
 pub fn draw_trail(chart: Chart, chart_property: &ChartProperties, debug: bool){
     let color = Color::new(1.0, 1.0, 1.0, 0.3);
     let debug_color_arc = Color::new(1.0, 0.0, 0.0, 0.3); // 画弧的颜色 白色
@@ -50,172 +73,82 @@ pub fn draw_trail(chart: Chart, chart_property: &ChartProperties, debug: bool){
     let start_distance = chart_property.start_distance;
     let end_distance = chart_property.end_distance;
 
-    for i in 0..chart.trail_distance.len() - 1{
-        let mut trail: TrailDistance;
-        let mut next_trail:TrailDistance;
-        trail = chart.trail_distance[i]; // 获取当前下标的trail
-        next_trail = chart.trail_distance[i + 1]; // 获取下一个trail
-
-        if next_trail.distance < start_distance || trail.distance > end_distance {
-            continue; // 如果下一个trail的距离小于显示的起始距离，或者当前trail的距离大于显示的结束距离，那么就不显示
-            // 该结果已经把所有没有交集的情况都排除了
-        }
-
-        if trail.distance >= start_distance && next_trail.distance < end_distance {
-            // 全部在内
-            for i in 0..100 {
-                let this_distance1 = trail.distance + (next_trail.distance - trail.distance) * i as f32 / 100.0;
-                let this_distance2 = trail.distance + (next_trail.distance - trail.distance) * (i + 1) as f32 / 100.0;
-                let time1 = chart.find_time_by_distance(this_distance1);
-                let time2 = chart.find_time_by_distance(this_distance2);
-
-                let radius1 = distance_to_radius(327.5, this_distance1, start_distance, end_distance);
-                let progress1 = (this_distance1 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree1 = chart.find_degree_by_2_trails(trail, next_trail, progress1);
-                let truedegree1  = 450.0 - degree1;
-                let (x1, y1) = (600.0 + radius1 * truedegree1.to_radians().cos(), 400.0 - radius1 * truedegree1.to_radians().sin());
-
-                let radius2 = distance_to_radius(327.5, this_distance2, start_distance, end_distance);
-                let progress2 = (this_distance2 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree2 = chart.find_degree_by_2_trails(trail, next_trail, progress2);
-                let truedegree2  = 450.0 - degree2;
-                let (x2, y2) = (600.0 + radius2 * truedegree2.to_radians().cos(), 400.0 - radius2 * truedegree2.to_radians().sin());
-                
-                let thickness = (6.0 * radius1 / 327.5).max(1.0);
-                if debug {
-                    draw_line(x1, y1, x2, y2, thickness, debug_color_inner);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, debug_color_inner);
-                }
-                else{
-                    draw_line(x1, y1, x2, y2, thickness, color);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, color);
-                }
-                
-            }
+    // trail按时间顺序两两相连，每一段按时间采样再算距离
+    // 倒流时距离会来回走，按距离采样会漏画或者连错
+    // 先按显示范围对应的时间段找出可能看得见的段，只采样这些段
+    let trails: &[TrailDistance] = chart.timeline().trail_by_time();
+    let samples: usize = 100;
+    let mut visible: Vec<usize> = chart.timeline().time_ranges_by_distance(start_distance, end_distance)
+        .into_iter()
+        .flat_map(|(a, b)| chart.timeline().trail_segments_in_time_range(a, b))
+        .collect();
+    visible.sort_unstable();
+    visible.dedup();
+
+    for i in visible {
+        let trail: TrailDistance = trails[i]; // 获取当前下标的trail
+        let next_trail: TrailDistance = trails[i + 1]; // 获取下一个trail
+        if next_trail.time <= trail.time {
+            continue;
         }
-        else if trail.distance < start_distance && next_trail.distance > start_distance && next_trail.distance < end_distance {
-            // 尾在内头不在内
-            for i in 0..100 {
-                let this_distance1 = start_distance + (next_trail.distance - start_distance) * i as f32 / 100.0;
-                let this_distance2 = start_distance + (next_trail.distance - start_distance) * (i + 1) as f32 / 100.0;
-                let time1 = chart.find_time_by_distance(this_distance1);
-                let time2 = chart.find_time_by_distance(this_distance2);
-                if(this_distance1 < start_distance || this_distance2 > end_distance){
-                    continue;
-                }
-                let radius1 = distance_to_radius(327.5, this_distance1, start_distance, end_distance);
-                let progress1 = (this_distance1 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree1 = chart.find_degree_by_2_trails(trail, next_trail, progress1);
-                let truedegree1  = 450.0 - degree1;
-                let (x1, y1) = (600.0 + radius1 * truedegree1.to_radians().cos(), 400.0 - radius1 * truedegree1.to_radians().sin());
-
-                
-                let radius2 = distance_to_radius(327.5, this_distance2, start_distance, end_distance);
-                let progress2 = (this_distance2 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree2 = chart.find_degree_by_2_trails(trail, next_trail, progress2);
-                let truedegree2  = 450.0 - degree2;
-                let (x2, y2) = (600.0 + radius2 * truedegree2.to_radians().cos(), 400.0 - radius2 * truedegree2.to_radians().sin());
-            
-                let thickness = (4.0 * radius1 / 327.5).max(1.0);
-                if debug {
-                    draw_line(x1, y1, x2, y2, thickness, debug_color_front);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, debug_color_front);
-                }
-                else{
-                    draw_line(x1, y1, x2, y2, thickness, color);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, color);
-                }
-                // draw_text(&format!("{:.2}", progress1), x1, y1, 20.0, WHITE);
-            }              
+        let segment: TrailSegment = TrailSegment::new(trail, next_trail);
+
+        let points: Vec<(f64, f64)> = (0..=samples)
+            .map(|j| {
+                let time = trail.time + (next_trail.time - trail.time) * j as f64 / samples as f64;
+                (time, chart.find_distance_by_time(time))
+            })
+            .collect();
+        if points.iter().all(|p| p.1 < start_distance) || points.iter().all(|p| p.1 > end_distance) {
+            continue; // 整段都不在显示范围内
         }
-        else if trail.distance > start_distance && trail.distance < end_distance && next_trail.distance > end_distance {
-            // draw_text(&format!("{:.1} {:.1}", trail.distance, next_trail.distance), 200.0, 200.0, 20.0, WHITE);
-            // 头在内尾不在内
-            for i in 0..100 {
-                let this_distance1 = trail.distance + (end_distance - trail.distance) * i as f32 / 100.0;
-                let this_distance2 = trail.distance + (end_distance - trail.distance) * (i + 1) as f32 / 100.0;
-                let time1 = chart.find_time_by_distance(this_distance1);
-                let time2 = chart.find_time_by_distance(this_distance2);
-                if(this_distance1 < start_distance || this_distance2 > end_distance){
-                    continue;
-                }
 
-                let radius1 = distance_to_radius(327.5, this_distance1, start_distance, end_distance);
-                let progress1 = (this_distance1 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree1 = chart.find_degree_by_2_trails(trail, next_trail, progress1);
-                let truedegree1  = 450.0 - degree1;
-                let (x1, y1) = (600.0 + radius1 * truedegree1.to_radians().cos(), 400.0 - radius1 * truedegree1.to_radians().sin());
-
-                let radius2 = distance_to_radius(327.5, this_distance2, start_distance, end_distance);
-                let progress2 = (this_distance2 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree2 = chart.find_degree_by_2_trails(trail, next_trail, progress2);
-                let truedegree2  = 450.0 - degree2;
-                let (x2, y2) = (600.0 + radius2 * truedegree2.to_radians().cos(), 400.0 - radius2 * truedegree2.to_radians().sin());
-            
-                let thickness = (4.0 * radius1 / 327.5).max(1.0);
-                if debug {
-                    draw_line(x1, y1, x2, y2, thickness, debug_color_back);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, debug_color_back);
-                }
-                else{
-                    draw_line(x1, y1, x2, y2, thickness, color);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, color);
-                }
-                
-            }
-        }
-        else if trail.distance <= start_distance && next_trail.distance > end_distance{ // 头尾都在外面
-            for i in 0.. 100 {
-                let this_distance1 = start_distance + (end_distance - start_distance) * i as f32 / 100.0;
-                let this_distance2 = start_distance + (end_distance - start_distance) * (i + 1) as f32 / 100.0;
-                let time1 = chart.find_time_by_distance(this_distance1);
-                let time2 = chart.find_time_by_distance(this_distance2);
-                if this_distance2 > end_distance || this_distance1 < start_distance{
-                    continue;
-                }
-                let radius1 = distance_to_radius(327.5, this_distance1, start_distance, end_distance);
-                let progress1 = (this_distance1 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree1 = chart.find_degree_by_2_trails(trail, next_trail, progress1);
-                let truedegree1  = 450.0 - degree1;
-                let (x1, y1) = (600.0 + radius1 * truedegree1.to_radians().cos(), 400.0 - radius1 * truedegree1.to_radians().sin());
-
-                let radius2 = distance_to_radius(327.5, this_distance2, start_distance, end_distance);
-                let progress2 = (this_distance2 - trail.distance) / (next_trail.distance - trail.distance);
-                let degree2 = chart.find_degree_by_2_trails(trail, next_trail, progress2);
-                let truedegree2  = 450.0 - degree2;
-                let (x2, y2) = (600.0 + radius2 * truedegree2.to_radians().cos(), 400.0 - radius2 * truedegree2.to_radians().sin());
-            
-                let thickness = (4.0 * radius1 / 327.5).max(1.0);
-                
-                // if ((x1 - x2).powi(2) + (y1 - y2).powi(2)) > 10.0 {
-                //     println!("{:.2} {:.2} {:.2} {:.2}", x1, y1, x2, y2);
-                //     continue;
-                // }
-                // TODO 把直线bug修了 （其实已修复，但是不知道会不会犯病）
-                if debug {
-                    // if i == 50{
-                    //     draw_text(&trail.time.to_string() , x1 + 5.0, y1 + 5.0, 20.0, debug_color_mid);
-                    //     draw_text(&next_trail.time.to_string() , x1 + 5.0, y1 + 30.0, 20.0, debug_color_mid);
-                    // }
-                    draw_line(x1, y1, x2, y2, thickness, debug_color_mid);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, debug_color_mid);
-                }
-                else{
-                    draw_line(x1, y1, x2, y2, thickness, color);
-                    draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, color);
-                }
+        // 距离单调时按距离算曲线进度，和find_degree_by_time保持一致；倒流的段只能按时间算
+        let increasing = points.windows(2).all(|w| w[1].1 > w[0].1);
+        let decreasing = points.windows(2).all(|w| w[1].1 < w[0].1);
+        let progress_of = |time: f64, distance: f64| -> f32 {
+            if increasing || decreasing {
+                segment.progress_at_distance(distance)
+            } else {
+                ((time - trail.time) / (next_trail.time - trail.time)) as f32
             }
+        };
+
+        let below = points.iter().any(|p| p.1 < start_distance);
+        let above = points.iter().any(|p| p.1 > end_distance);
+        let (debug_color, width) = match (below, above) {
+            (false, false) => (debug_color_inner, 6.0), // 全部在内
+            (true, false) => (debug_color_front, 4.0), // 尾在内头不在内
+            (false, true) => (debug_color_back, 4.0), // 头在内尾不在内
+            (true, true) => (debug_color_mid, 4.0), // 头尾都在外面
+        };
+        let line_color = if debug { debug_color } else { color };
+
+        for j in 0..samples {
+            let (time1, this_distance1, time2, this_distance2) = match clip_sample(points[j], points[j + 1], start_distance, end_distance) {
+                Some(clipped) => clipped,
+                None => continue,
+            };
+
+            let radius1 = distance_to_radius(327.5, this_distance1, start_distance, end_distance);
+            let degree1 = segment.degree_at(progress_of(time1, this_distance1));
+            let truedegree1  = 450.0 - degree1;
+            let (x1, y1) = (600.0 + radius1 * truedegree1.to_radians().cos(), 400.0 - radius1 * truedegree1.to_radians().sin());
+
+            let radius2 = distance_to_radius(327.5, this_distance2, start_distance, end_distance);
+            let degree2 = segment.degree_at(progress_of(time2, this_distance2));
+            let truedegree2  = 450.0 - degree2;
+            let (x2, y2) = (600.0 + radius2 * truedegree2.to_radians().cos(), 400.0 - radius2 * truedegree2.to_radians().sin());
+
+            let thickness = (width * radius1 / 327.5).max(1.0);
+            draw_line(x1, y1, x2, y2, thickness, line_color);
+            draw_line(1200.0 - x1, 800.0 - y1, 1200.0 - x2, 800.0 - y2, thickness, line_color);
         }
     }
     
     let start_chart_time = chart_property.start_chart_time;
     let end_chart_time = chart_property.end_chart_time;
-    for i in 0..chart.note.len() {
-        // continue;
-        let note = &chart.note[i];
-        if chart.find_distance_by_time(note.get_time()) < start_distance || chart.find_distance_by_time(note.get_time()) > end_distance{
-            continue;
-        }
+    for note in chart.notes_in_distance_range(start_distance, end_distance) {
         match note {
             Note::Tap(tap) => { 
                 continue;
@@ -245,7 +178,7 @@ pub fn draw_trail(chart: Chart, chart_property: &ChartProperties, debug: bool){
                     if this_distance < start_distance || this_distance > end_distance { // 弧必须在显示范围内
                         continue;
                     }
-                    let thickness = 2.0 + 2.0 * (1.0 - (this_distance - start_distance) / (end_distance - start_distance));
+                    let thickness = 2.0 + 2.0 * (1.0 - ((this_distance - start_distance) / (end_distance - start_distance)) as f32);
 
                     if debug {
                         if trail.delta.abs() >= 180.0{
@@ -429,6 +362,49 @@ pub fn update_slider(slider: &mut Slider) -> bool {
     flag
 }
 
+// 画节拍线，每1/num_divisions拍一个圆环，整拍的颜色深一点，小节线最粗
+// 圆环和note一样经过find_distance_by_time和distance_to_radius，变速时网格会跟着变形
 pub fn draw_bar_line(chart: Chart, chart_property: &ChartProperties, num_divisions: i32, debug: bool){
-    
+    let measure_color = Color::new(1.0, 1.0, 1.0, 0.5);
+    let beat_color = Color::new(1.0, 1.0, 1.0, 0.25);
+    let division_color = Color::new(1.0, 1.0, 1.0, 0.1);
+    let start_distance = chart_property.start_distance;
+    let end_distance = chart_property.end_distance;
+    let num_divisions = num_divisions.max(1) as f64;
+
+    let start_beat = chart.chart_time_to_beat(chart_property.start_chart_time);
+    let end_beat = chart.chart_time_to_beat(chart_property.end_chart_time);
+    let mut index = (start_beat * num_divisions).ceil() as i64;
+    loop {
+        let beat = index as f64 / num_divisions;
+        if beat > end_beat {
+            break;
+        }
+        index += 1;
+
+        let time = chart.beat_to_chart_time(beat);
+        let this_distance = chart.find_distance_by_time(time);
+        if this_distance < start_distance || this_distance > end_distance {
+            continue;
+        }
+        let radius = distance_to_radius(327.5, this_distance, start_distance, end_distance);
+        if radius <= 33.00 {
+            continue;
+        }
+
+        let measure = chart.beat_to_measure(beat);
+        let is_measure = (measure - measure.round()).abs() < 1e-6;
+        let is_beat = (beat - beat.round()).abs() < 1e-6;
+        let (color, thickness) = if is_measure {
+            (measure_color, (4.0 * radius / 327.5).max(1.5))
+        } else if is_beat {
+            (beat_color, (2.0 * radius / 327.5).max(1.0))
+        } else {
+            (division_color, (2.0 * radius / 327.5).max(1.0))
+        };
+        draw_circle_lines(600.0, 400.0, radius, thickness, color);
+        if debug && is_measure {
+            draw_text(&format!("{}", measure.round()), 600.0 + radius + 4.0, 400.0, 20.0, measure_color);
+        }
+    }
 }
\ No newline at end of file